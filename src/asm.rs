@@ -49,6 +49,64 @@ pub fn opcode(s: &str) -> u8 {
 }
 
 
+pub fn mnemonic(op: u8) -> &'static str {
+	match op {
+		0x0  => "sto",
+		0x1  => "loa",
+		0x2  => "add",
+		0x3  => "sub",
+		0x4  => "mul",
+		0x5  => "idiv",
+		0x6  => "addn",
+		0x7  => "subn",
+		0x8  => "muln",
+		0x9  => "divn",
+		0xa  => "addz",
+		0xb  => "addc",
+		0xc  => "adds",
+		0xd  => "notr",
+		0xe  => "andr",
+		0xf  => "orr",
+		0x10 => "xorr",
+		0x11 => "shl",
+		0x12 => "shr",
+		0x13 => "andn",
+		0x14 => "orn",
+		0x15 => "xorn",
+		0x16 => "shln",
+		0x17 => "shrn",
+		0x18 => "push",
+		0x19 => "pop",
+		0x1a => "call",
+		0x1b => "iint",
+		0x1c => "iret",
+		0x1d => "chst",
+		0x1e => "lost",
+		0x1f => "chtp",
+		0x20 => "lotp",
+		0x21 => "chflag",
+		0x22 => "loflag",
+		0x23 => "utok",
+		0x24 => "ktou",
+		0x25 => "setsyscall",
+		0x26 => "syscall",
+		_    => unreachable!()
+	}
+}
+
+
+// opcode and operand size of an instruction name with an optional B, S, I or L suffix
+pub fn instruction(s: &str) -> (u8, u8) {
+	match s.as_bytes().last() {
+		Some(b'B') => (opcode(&s[..s.len() - 1]), 0),
+		Some(b'S') => (opcode(&s[..s.len() - 1]), 1),
+		Some(b'I') => (opcode(&s[..s.len() - 1]), 2),
+		Some(b'L') => (opcode(&s[..s.len() - 1]), 3),
+		_ => (opcode(s), 3),
+	}
+}
+
+
 pub fn register(s: &str) -> u8 {
 	match s {
 		"r0"   => 0,
@@ -85,54 +143,112 @@ pub fn datatype(s: &str) -> u8 {
 }
 
 
+pub fn directive(s: &str) -> &'static str {
+	match s {
+		".global" | ".globl" => ".global",
+		".extern"            => ".extern",
+		".section"           => ".section",
+		".text"              => ".text",
+		".data"              => ".data",
+		".bss"               => ".bss",
+		".org"               => ".org",
+		".align" | ".balign" => ".align",
+		".space" | ".skip"   => ".space",
+		".fill"              => ".fill",
+		".base"              => ".base",
+		".if"                => ".if",
+		".elseif"            => ".elseif",
+		".else"              => ".else",
+		".endif"             => ".endif",
+		".ifdef"             => ".ifdef",
+		".ifndef"            => ".ifndef",
+		".equ"               => ".equ",
+		".set"               => ".set",
+		_                    => unreachable!(),
+	}
+}
+
+
+// minimum and maximum number of arguments
+pub fn directive_args(s: &str) -> (usize, usize) {
+	match s {
+		".global"  => (1, usize::MAX),
+		".extern"  => (1, usize::MAX),
+		".section" => (1, 2),
+		".text"    => (0, 0),
+		".data"    => (0, 0),
+		".bss"     => (0, 0),
+		".org"     => (1, 2),
+		".align"   => (1, 2),
+		".space"   => (1, 2),
+		".fill"    => (1, 3),
+		".base"    => (1, 1),
+		".if"      => (1, 1),
+		".elseif"  => (1, 1),
+		".else"    => (0, 0),
+		".endif"   => (0, 0),
+		".ifdef"   => (1, 1),
+		".ifndef"  => (1, 1),
+		".equ"     => (2, 2),
+		".set"     => (2, 2),
+		_         => unreachable!(),
+	}
+}
+
+
+#[allow(clippy::identity_op)]
+pub fn instr_size(op: u8) -> u64 {
+	match op {
+		0  => 4 * 3,
+		1  => 4 * 3,
+		2  => 4 * 1,
+		3  => 4 * 1,
+		4  => 4 * 1,
+		5  => 4 * 1,
+		6  => 4 * 3,
+		7  => 4 * 3,
+		8  => 4 * 3,
+		9  => 4 * 3,
+		10 => 4 * 3,
+		11 => 4 * 3,
+		12 => 4 * 3,
+		13 => 4 * 1,
+		14 => 4 * 1,
+		15 => 4 * 1,
+		16 => 4 * 1,
+		17 => 4 * 1,
+		18 => 4 * 1,
+		19 => 4 * 3,
+		20 => 4 * 3,
+		21 => 4 * 3,
+		22 => 4 * 3,
+		23 => 4 * 3,
+		24 => 4 * 1,
+		25 => 4 * 1,
+		26 => 4 * 1,
+		27 => 4 * 1,
+		28 => 4 * 1,
+		29 => 4 * 1,
+		30 => 4 * 1,
+		31 => 4 * 1,
+		32 => 4 * 1,
+		33 => 4 * 1,
+		34 => 4 * 1,
+		35 => 4 * 1,
+		36 => 4 * 1,
+		37 => 4 * 1,
+		38 => 4 * 1,
+		_  => unreachable!()
+	}
+}
+
+
 pub fn get_size(e: &Expr) -> u64 {
 	match &e.kind {
-		ExprKind::Instruction(op, _, _) => {
-			match op {
-				0  => 4 * 3,
-				1  => 4 * 3,
-				2  => 4 * 1,
-				3  => 4 * 1,
-				4  => 4 * 1,
-				5  => 4 * 1,
-				6  => 4 * 3,
-				7  => 4 * 3,
-				8  => 4 * 3,
-				9  => 4 * 3,
-				10 => 4 * 3,
-				11 => 4 * 3,
-				12 => 4 * 3,
-				13 => 4 * 1,
-				14 => 4 * 1,
-				15 => 4 * 1,
-				16 => 4 * 1,
-				17 => 4 * 1,
-				18 => 4 * 1,
-				19 => 4 * 3,
-				20 => 4 * 3,
-				21 => 4 * 3,
-				22 => 4 * 3,
-				23 => 4 * 3,
-				24 => 4 * 1,
-				25 => 4 * 1,
-				26 => 4 * 1,
-				27 => 4 * 1,
-				28 => 4 * 1,
-				29 => 4 * 1,
-				30 => 4 * 1,
-				31 => 4 * 1,
-				32 => 4 * 1,
-				33 => 4 * 1,
-				34 => 4 * 1,
-				35 => 4 * 1,
-				36 => 4 * 1,
-				37 => 4 * 1,
-				38 => 4 * 1,
-				_  => unreachable!()
-			}
-		},
+		ExprKind::Instruction(op, _, _) => instr_size(*op),
 		ExprKind::Data(size, vals) => *size as u64 * vals.len() as u64,
 		ExprKind::Label(_) => 0,
+		ExprKind::Directive(_, _) => 0,
 		_ => unreachable!(),
 	}
 }
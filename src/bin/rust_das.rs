@@ -0,0 +1,56 @@
+use rust_as::diag::{Diagnostic, Source};
+use rust_as::disasm::disassemble;
+use rust_as::listing::read_map;
+use rust_as::token::parse_number;
+
+
+fn fail(diags: &[Diagnostic]) -> ! {
+	let source = Source::new("", "");
+	for d in diags {
+		eprint!("{}", d.render(&source));
+	}
+	std::process::exit(1);
+}
+
+
+fn main() {
+	let mut input = None;
+	let mut base = 0;
+	let mut map = None;
+
+	let mut args = std::env::args().skip(1);
+
+	while let Some(arg) = args.next() {
+		let mut value = || args.next()
+			.unwrap_or_else(|| fail(&[Diagnostic::global(format!("option `{arg}` expects a value"))]));
+
+		match arg.as_str() {
+			"--base" | "-Ttext" => {
+				let value = value();
+				base = parse_number(&value)
+					.unwrap_or_else(|| fail(&[Diagnostic::global(format!("invalid address `{value}`"))])) as u64;
+			},
+			"-M" => map = Some(value()),
+			_ if arg.starts_with('-') => fail(&[Diagnostic::global(format!("unknown option `{arg}`"))]),
+			_ if input.is_none() => input = Some(arg),
+			_ => fail(&[Diagnostic::global(format!("unexpected argument `{arg}`"))]),
+		}
+	}
+
+	let Some(input) = input else {
+		fail(&[Diagnostic::global("expected input filename")]);
+	};
+
+	let image = std::fs::read(&input)
+		.unwrap_or_else(|e| fail(&[Diagnostic::global(format!("cannot read `{input}`: {e}"))]));
+
+	let symbols = match map {
+		Some(map) => std::fs::read_to_string(&map)
+			.map_err(|e| e.to_string())
+			.and_then(|text| read_map(&text))
+			.unwrap_or_else(|e| fail(&[Diagnostic::global(format!("cannot read `{map}`: {e}"))])),
+		None => vec![],
+	};
+
+	print!("{}", disassemble(&image, base, &symbols));
+}
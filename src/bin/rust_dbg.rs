@@ -0,0 +1,105 @@
+use std::io::{BufRead, Write};
+
+use rust_as::debug::Debugger;
+use rust_as::diag::{Diagnostic, Source};
+use rust_as::emu::{Machine, MAX_MEMORY};
+use rust_as::listing::{read_debug_lines, read_listing, read_map};
+use rust_as::token::parse_number;
+
+
+fn fail(diags: &[Diagnostic]) -> ! {
+	let source = Source::new("", "");
+	for d in diags {
+		eprint!("{}", d.render(&source));
+	}
+	std::process::exit(1);
+}
+
+
+fn read<T>(name: &str, parse: fn(&str) -> Result<T, String>) -> T {
+	std::fs::read_to_string(name)
+		.map_err(|e| e.to_string())
+		.and_then(|text| parse(&text))
+		.unwrap_or_else(|e| fail(&[Diagnostic::global(format!("cannot read `{name}`: {e}"))]))
+}
+
+
+fn main() {
+	let mut input = None;
+	let mut base = 0;
+	let mut memory = 0x100000;
+	let mut steps = None;
+	let mut map = None;
+	let mut listing = None;
+	let mut debug = None;
+
+	let mut args = std::env::args().skip(1);
+
+	while let Some(arg) = args.next() {
+		let mut value = || args.next()
+			.unwrap_or_else(|| fail(&[Diagnostic::global(format!("option `{arg}` expects a value"))]));
+
+		match arg.as_str() {
+			"--base" | "-Ttext" | "--memory" | "--steps" => {
+				let value = value();
+				let n = parse_number(&value)
+					.filter(|n| *n >= 0)
+					.unwrap_or_else(|| fail(&[Diagnostic::global(format!("invalid value `{value}` for `{arg}`"))])) as u64;
+				match arg.as_str() {
+					"--memory" => memory = n,
+					"--steps" => steps = Some(n),
+					_ => base = n,
+				}
+			},
+			"-M" => map = Some(value()),
+			"-l" => listing = Some(value()),
+			"-g" => debug = Some(value()),
+			_ if arg.starts_with('-') => fail(&[Diagnostic::global(format!("unknown option `{arg}`"))]),
+			_ if input.is_none() => input = Some(arg),
+			_ => fail(&[Diagnostic::global(format!("unexpected argument `{arg}`"))]),
+		}
+	}
+
+	let Some(input) = input else {
+		fail(&[Diagnostic::global("expected input filename")]);
+	};
+
+	let image = std::fs::read(&input)
+		.unwrap_or_else(|e| fail(&[Diagnostic::global(format!("cannot read `{input}`: {e}"))]));
+
+	if memory > MAX_MEMORY {
+		fail(&[Diagnostic::global(format!("memory size {memory:#x} is larger than {MAX_MEMORY:#x} bytes"))]);
+	}
+
+	let mut machine = Machine::new(memory as usize);
+	if let Err(e) = machine.load(&image, base) {
+		fail(&[Diagnostic::global(format!("cannot load `{input}`: {e}"))]);
+	}
+
+	let symbols = map.map(|name| read(&name, read_map)).unwrap_or_default();
+	let lines = listing.map(|name| read(&name, read_listing)).unwrap_or_default();
+
+	let mut debugger = Debugger::new(machine, symbols, lines);
+	debugger.origins = debug.map(|name| read(&name, read_debug_lines)).unwrap_or_default();
+	if let Some(steps) = steps {
+		debugger.step_limit = steps;
+	}
+	print!("{}", debugger.command("list").unwrap().unwrap());
+
+	let stdin = std::io::stdin();
+	loop {
+		print!("(dbg) ");
+		let _ = std::io::stdout().flush();
+
+		let mut line = String::new();
+		if stdin.lock().read_line(&mut line).unwrap_or(0) == 0 {
+			break;
+		}
+
+		match debugger.command(&line) {
+			Some(Ok(out)) => print!("{out}"),
+			Some(Err(e)) => eprintln!("error: {e}"),
+			None => break,
+		}
+	}
+}
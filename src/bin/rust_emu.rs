@@ -0,0 +1,95 @@
+use rust_as::diag::{Diagnostic, Source};
+use rust_as::emu::{Machine, Stop, MAX_MEMORY, SP, PC};
+use rust_as::gdb::Stub;
+use rust_as::token::parse_number;
+
+
+fn fail(diags: &[Diagnostic]) -> ! {
+	let source = Source::new("", "");
+	for d in diags {
+		eprint!("{}", d.render(&source));
+	}
+	std::process::exit(1);
+}
+
+
+fn main() {
+	let mut input = None;
+	let mut base = 0;
+	let mut memory = 0x100000;
+	let mut steps = 1_000_000;
+	let mut gdb = None;
+
+	let mut args = std::env::args().skip(1);
+
+	while let Some(arg) = args.next() {
+		let mut number = || {
+			let value = args.next()
+				.unwrap_or_else(|| fail(&[Diagnostic::global(format!("option `{arg}` expects a value"))]));
+			parse_number(&value)
+				.filter(|n| *n >= 0)
+				.unwrap_or_else(|| fail(&[Diagnostic::global(format!("invalid value `{value}` for `{arg}`"))])) as u64
+		};
+
+		match arg.as_str() {
+			"--base" | "-Ttext" => base = number(),
+			"--memory" => memory = number(),
+			"--steps" => steps = number(),
+			"--gdb" => {
+				let port = number();
+				gdb = Some(u16::try_from(port).unwrap_or_else(|_| {
+					fail(&[Diagnostic::global(format!("invalid port {port} for `{arg}`"))])
+				}));
+			},
+			_ if arg.starts_with('-') => fail(&[Diagnostic::global(format!("unknown option `{arg}`"))]),
+			_ if input.is_none() => input = Some(arg),
+			_ => fail(&[Diagnostic::global(format!("unexpected argument `{arg}`"))]),
+		}
+	}
+
+	let Some(input) = input else {
+		fail(&[Diagnostic::global("expected input filename")]);
+	};
+
+	let image = std::fs::read(&input)
+		.unwrap_or_else(|e| fail(&[Diagnostic::global(format!("cannot read `{input}`: {e}"))]));
+
+	if memory > MAX_MEMORY {
+		fail(&[Diagnostic::global(format!("memory size {memory:#x} is larger than {MAX_MEMORY:#x} bytes"))]);
+	}
+
+	let mut machine = Machine::new(memory as usize);
+	if let Err(e) = machine.load(&image, base) {
+		fail(&[Diagnostic::global(format!("cannot load `{input}`: {e}"))]);
+	}
+
+	if let Some(port) = gdb {
+		let listener = std::net::TcpListener::bind(("127.0.0.1", port))
+			.unwrap_or_else(|e| fail(&[Diagnostic::global(format!("cannot listen on port {port}: {e}"))]));
+		eprintln!("waiting for gdb on 127.0.0.1:{port}");
+
+		if let Err(e) = Stub::new(machine).serve(&listener) {
+			fail(&[Diagnostic::global(format!("gdb connection failed: {e}"))]);
+		}
+		return;
+	}
+
+	let stop = machine.run(steps);
+
+	for n in 0..16 {
+		let name = match n {
+			SP => "sp".to_string(),
+			PC => "pc".to_string(),
+			_ => format!("r{n}"),
+		};
+		println!("{name:>4} {:016x}", machine.reg(n));
+	}
+	println!("{:>4} {:016x}", "flag", machine.flags);
+	println!("{:>4} {}", "mode", if machine.user { "user" } else { "kernel" });
+
+	match stop {
+		Stop::Halted => println!("halted after {} steps", machine.steps),
+		Stop::StepLimit => fail(&[Diagnostic::global(format!("step limit of {steps} reached"))]),
+		Stop::Fault { pc, fault } => fail(&[Diagnostic::global(format!("{fault} at {pc:#x}"))]),
+	}
+}
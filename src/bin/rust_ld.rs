@@ -0,0 +1,92 @@
+use rust_as::diag::{Diagnostic, Source};
+use rust_as::link::{link, Input, LinkOptions};
+use rust_as::object::Object;
+use rust_as::token::parse_number;
+
+
+fn fail(diags: &[Diagnostic]) -> ! {
+	let source = Source::new("", "");
+	for d in diags {
+		eprint!("{}", d.render(&source));
+	}
+	std::process::exit(1);
+}
+
+
+fn address(s: &str) -> Result<u64, Diagnostic> {
+	parse_number(s)
+		.map(|n| n as u64)
+		.ok_or(Diagnostic::global(format!("invalid address `{s}`")))
+}
+
+
+fn parse_args() -> Result<(Vec<String>, String, LinkOptions), Diagnostic> {
+	let mut inputs = vec![];
+	let mut output = None;
+	let mut opts = LinkOptions {
+		base: 0,
+		section_starts: vec![],
+	};
+
+	let mut args = std::env::args().skip(1);
+
+	while let Some(arg) = args.next() {
+		let mut value = |name: &str| args.next()
+			.ok_or(Diagnostic::global(format!("option `{name}` expects a value")));
+
+		match arg.as_str() {
+			"-o" => output = Some(value("-o")?),
+			"--base" | "-Ttext" => opts.base = address(&value(&arg)?)?,
+			"--section-start" => {
+				let v = value("--section-start")?;
+				let Some((name, addr)) = v.split_once('=') else {
+					return Err(Diagnostic::global(format!("expected NAME=ADDRESS, found `{v}`")));
+				};
+				opts.section_starts.push((name.to_string(), address(addr)?));
+			},
+			_ if arg.starts_with('-') => return Err(Diagnostic::global(format!("unknown option `{arg}`"))),
+			_ => inputs.push(arg),
+		}
+	}
+
+	if inputs.is_empty() {
+		return Err(Diagnostic::global("expected input objects"));
+	}
+
+	Ok((inputs, output.ok_or(Diagnostic::global("expected output filename (-o)"))?, opts))
+}
+
+
+fn main() {
+	let (names, output, opts) = match parse_args() {
+		Ok(args) => args,
+		Err(e) => fail(&[e]),
+	};
+
+	let mut inputs = vec![];
+	let mut diags = vec![];
+
+	for name in names {
+		let object = std::fs::read(&name)
+			.map_err(|e| e.to_string())
+			.and_then(|bytes| Object::read(&bytes));
+
+		match object {
+			Ok(object) => inputs.push(Input { name, object }),
+			Err(e) => diags.push(Diagnostic::global(format!("cannot read `{name}`: {e}"))),
+		}
+	}
+
+	if !diags.is_empty() {
+		fail(&diags);
+	}
+
+	let image = match link(&inputs, &opts) {
+		Ok(image) => image,
+		Err(diags) => fail(&diags),
+	};
+
+	if let Err(e) = std::fs::write(&output, image) {
+		fail(&[Diagnostic::global(format!("cannot write `{output}`: {e}"))]);
+	}
+}
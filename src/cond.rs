@@ -0,0 +1,96 @@
+use logos::Span;
+
+use crate::diag::Diagnostic;
+use crate::expr::{Expr, ExprKind, Symbols};
+
+
+// an open .if block
+struct Block {
+	span: Span,
+	// the block containing the .if is assembled
+	outer: bool,
+	// one of the branches was already chosen
+	taken: bool,
+	seen_else: bool,
+}
+
+
+pub fn is_conditional(e: &Expr) -> bool {
+	matches!(e.kind, ExprKind::Directive(".if" | ".elseif" | ".else" | ".endif" | ".ifdef" | ".ifndef", _))
+}
+
+
+fn defined(name: &str, at: usize, syms: &Symbols) -> bool {
+	syms.label(name, at).is_some() || syms.definition(name, at).is_some()
+}
+
+
+// decides which items are assembled with the labels of the previous layout pass.
+// conditional directives are always kept so that `$` in a condition has a place.
+// a condition that cannot be evaluated yet counts as false unless `strict` is
+// set, then the error is reported instead
+pub fn select(items: &[Expr], syms: &Symbols, strict: bool) -> Result<Vec<bool>, Vec<Diagnostic>> {
+	let mut keep = Vec::with_capacity(items.len());
+	let mut blocks: Vec<Block> = vec![];
+	let mut active = true;
+	let mut diags = vec![];
+
+	let condition = |e: &Expr, diags: &mut Vec<Diagnostic>| {
+		let ExprKind::Directive(name, args) = &e.kind else {unreachable!()};
+		match (*name, &args[0].kind) {
+			(".ifdef", ExprKind::Id(id)) => defined(id, e.span.start, syms),
+			(".ifndef", ExprKind::Id(id)) => !defined(id, e.span.start, syms),
+			(".if" | ".elseif", _) => match args[0].eval(syms) {
+				Ok(v) => v != 0,
+				Err(e) => {
+					if strict {
+						diags.push(e);
+					}
+					false
+				},
+			},
+			_ => unreachable!(),
+		}
+	};
+
+	for i in items {
+		let ExprKind::Directive(name, _) = i.kind else {
+			keep.push(active);
+			continue;
+		};
+
+		match name {
+			".if" | ".ifdef" | ".ifndef" => {
+				let taken = active && condition(i, &mut diags);
+				blocks.push(Block { span: i.span.clone(), outer: active, taken, seen_else: false });
+				active = taken;
+			},
+			".elseif" | ".else" => match blocks.last_mut() {
+				None => diags.push(Diagnostic::error(i.span.clone(), format!("`{name}` without `.if`"))),
+				Some(b) if b.seen_else => diags.push(Diagnostic::error(i.span.clone(), format!("`{name}` after `.else`"))),
+				Some(b) => {
+					active = b.outer && !b.taken && (name == ".else" || condition(i, &mut diags));
+					b.taken |= active;
+					b.seen_else = name == ".else";
+				},
+			},
+			".endif" => match blocks.pop() {
+				None => diags.push(Diagnostic::error(i.span.clone(), "`.endif` without `.if`")),
+				Some(b) => active = b.outer,
+			},
+			_ => {},
+		}
+
+		keep.push(active || is_conditional(i));
+	}
+
+	for b in blocks {
+		diags.push(Diagnostic::error(b.span, "`.if` without `.endif`"));
+	}
+
+	if diags.is_empty() {
+		Ok(keep)
+	} else {
+		Err(diags)
+	}
+}
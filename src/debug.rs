@@ -0,0 +1,261 @@
+use std::fmt::Write;
+
+use crate::disasm::format;
+use crate::emu::{Machine, Stop, PC, SP};
+use crate::token::parse_number;
+
+
+pub struct Watchpoint {
+	pub address: u64,
+	pub size: u8,
+	pub value: u64,
+}
+
+pub struct Debugger {
+	pub machine: Machine,
+	// (address, name) pairs from a symbol map
+	pub symbols: Vec<(u64, String)>,
+	// (address, line, source text) from a listing
+	pub lines: Vec<(u64, usize, String)>,
+	// (address, file, line) from a debug line file
+	pub origins: Vec<(u64, String, usize)>,
+	pub breakpoints: Vec<u64>,
+	pub watchpoints: Vec<Watchpoint>,
+	// set once the program halted or faulted
+	pub stopped: Option<Stop>,
+	// how many instructions `continue` runs before giving control back
+	pub step_limit: u64,
+}
+
+
+const HELP: &str = "\
+break ADDR|LABEL     set a breakpoint
+watch ADDR [SIZE]    stop when the SIZE-byte value at ADDR changes
+delete [N]           remove breakpoint N, or all breakpoints and watchpoints
+unwatch N            remove watchpoint N
+info                 list breakpoints and watchpoints
+step [N]             execute N instructions
+continue             run until a breakpoint, watchpoint, halt, fault or the step limit
+regs                 show registers
+x ADDR [LEN]         dump LEN bytes of memory
+list                 show the source line at pc
+quit                 leave the debugger
+";
+
+
+fn register_name(n: u8) -> String {
+	match n {
+		SP => "sp".to_string(),
+		PC => "pc".to_string(),
+		_  => format!("r{n}"),
+	}
+}
+
+
+impl Debugger {
+	pub fn new(machine: Machine, symbols: Vec<(u64, String)>, lines: Vec<(u64, usize, String)>) -> Self {
+		Debugger {
+			machine,
+			symbols,
+			lines,
+			origins: vec![],
+			breakpoints: vec![],
+			watchpoints: vec![],
+			stopped: None,
+			step_limit: 1_000_000,
+		}
+	}
+
+	// a label from the symbol map or a number
+	pub fn resolve(&self, arg: &str) -> Result<u64, String> {
+		self.symbols.iter()
+			.find(|(_, name)| name == arg)
+			.map(|(address, _)| *address)
+			.or_else(|| parse_number(arg).map(|n| n as u64))
+			.ok_or_else(|| format!("unknown label or address `{arg}`"))
+	}
+
+	// `label+offset` for the closest label at or below `address`
+	fn location(&self, address: u64) -> String {
+		match self.symbols.iter().filter(|(a, _)| *a <= address).max_by_key(|(a, _)| *a) {
+			Some((a, name)) if *a == address => name.clone(),
+			Some((a, name)) => format!("{name}+{:#x}", address - a),
+			None => format!("{address:#x}"),
+		}
+	}
+
+	fn current(&self) -> String {
+		let pc = self.machine.reg(PC);
+		let instr = match self.machine.fetch() {
+			Ok(i) => format(&i, &self.symbols),
+			Err(e) => e.to_string(),
+		};
+
+		let mut out = format!("{pc:016x} <{}>  {instr}", self.location(pc));
+		if let Some((_, file, line)) = self.origins.iter().find(|(a, _, _)| *a == pc) {
+			let _ = write!(out, "  at {file}:{line}");
+		}
+		out.push('\n');
+		if let Some((_, line, text)) = self.lines.iter().find(|(a, _, _)| *a == pc) {
+			let _ = writeln!(out, "{line:>5}  {text}");
+		}
+		out
+	}
+
+	// executes one instruction, returning why execution should stop
+	fn step_one(&mut self) -> Option<String> {
+		if let Some(stop) = self.stopped {
+			return Some(describe(stop));
+		}
+
+		if let Some(stop) = self.machine.step() {
+			self.stopped = Some(stop);
+			return Some(describe(stop));
+		}
+
+		let mut hit = None;
+		for (n, w) in self.watchpoints.iter_mut().enumerate() {
+			let value = self.machine.read(w.address, w.size).unwrap_or(w.value);
+			if value != w.value {
+				hit = Some(format!("watchpoint {n} at {:#x}: {:#x} -> {value:#x}", w.address, w.value));
+				w.value = value;
+			}
+		}
+		hit
+	}
+
+	fn step(&mut self, count: u64) -> String {
+		for _ in 0..count {
+			if let Some(reason) = self.step_one() {
+				return format!("{reason}\n{}", self.current());
+			}
+		}
+		self.current()
+	}
+
+	fn cont(&mut self) -> String {
+		for _ in 0..self.step_limit {
+			if let Some(reason) = self.step_one() {
+				return format!("{reason}\n{}", self.current());
+			}
+
+			let pc = self.machine.reg(PC);
+			if let Some(n) = self.breakpoints.iter().position(|b| *b == pc) {
+				return format!("breakpoint {n} at {}\n{}", self.location(pc), self.current());
+			}
+		}
+
+		format!("step limit of {} reached\n{}", self.step_limit, self.current())
+	}
+
+	fn regs(&self) -> String {
+		let mut out = String::new();
+		for n in 0..16 {
+			let _ = writeln!(out, "{:>4} {:016x}", register_name(n), self.machine.reg(n));
+		}
+		let _ = writeln!(out, "{:>4} {:016x}", "flag", self.machine.flags);
+		let _ = writeln!(out, "{:>4} {}", "mode", if self.machine.user { "user" } else { "kernel" });
+		out
+	}
+
+	fn dump(&self, address: u64, len: u64) -> Result<String, String> {
+		let mut out = String::new();
+
+		for line in (address..address.saturating_add(len)).step_by(16) {
+			let end = line.saturating_add(16).min(address.saturating_add(len));
+			let bytes: Result<Vec<u64>, _> = (line..end).map(|a| self.machine.read(a, 0)).collect();
+			let bytes = bytes.map_err(|e| e.to_string())?;
+			let hex: Vec<String> = bytes.iter().map(|b| format!("{b:02x}")).collect();
+			let _ = writeln!(out, "{line:016x}  {}", hex.join(" "));
+		}
+
+		Ok(out)
+	}
+
+	// runs one command line and returns its output, None means quit
+	pub fn command(&mut self, line: &str) -> Option<Result<String, String>> {
+		let args: Vec<&str> = line.split_whitespace().collect();
+		let Some((&cmd, args)) = args.split_first() else {
+			return Some(Ok(String::new()));
+		};
+
+		let number = |n: Option<&&str>, default: u64| match n {
+			Some(n) => parse_number(n).map(|n| n as u64).ok_or_else(|| format!("invalid number `{n}`")),
+			None => Ok(default),
+		};
+
+		let result = match (cmd, args) {
+			("q" | "quit", []) => return None,
+			("h" | "help", []) => Ok(HELP.to_string()),
+			("b" | "break", [at]) => self.resolve(at).map(|address| {
+				self.breakpoints.push(address);
+				format!("breakpoint {} at {}\n", self.breakpoints.len() - 1, self.location(address))
+			}),
+			("w" | "watch", [at, rest @ ..]) if rest.len() <= 1 => {
+				let size = match number(rest.first(), 8) {
+					Ok(1) => Ok(0),
+					Ok(2) => Ok(1),
+					Ok(4) => Ok(2),
+					Ok(8) => Ok(3),
+					Ok(n) => Err(format!("watchpoint size must be 1, 2, 4 or 8, found {n}")),
+					Err(e) => Err(e),
+				};
+				size.and_then(|size| {
+					let address = self.resolve(at)?;
+					let value = self.machine.read(address, size).map_err(|e| e.to_string())?;
+					self.watchpoints.push(Watchpoint { address, size, value });
+					Ok(format!("watchpoint {} at {}\n", self.watchpoints.len() - 1, self.location(address)))
+				})
+			},
+			("d" | "delete", []) => {
+				self.breakpoints.clear();
+				self.watchpoints.clear();
+				Ok(String::new())
+			},
+			("d" | "delete", [n]) => number(Some(n), 0).and_then(|n| {
+				if (n as usize) < self.breakpoints.len() {
+					self.breakpoints.remove(n as usize);
+					Ok(String::new())
+				} else {
+					Err(format!("no breakpoint {n}"))
+				}
+			}),
+			("unwatch", [n]) => number(Some(n), 0).and_then(|n| {
+				if (n as usize) < self.watchpoints.len() {
+					self.watchpoints.remove(n as usize);
+					Ok(String::new())
+				} else {
+					Err(format!("no watchpoint {n}"))
+				}
+			}),
+			("i" | "info", []) => {
+				let mut out = String::new();
+				for (n, b) in self.breakpoints.iter().enumerate() {
+					let _ = writeln!(out, "breakpoint {n} at {}", self.location(*b));
+				}
+				for (n, w) in self.watchpoints.iter().enumerate() {
+					let _ = writeln!(out, "watchpoint {n} at {} ({} bytes)", self.location(w.address), 1 << w.size);
+				}
+				Ok(out)
+			},
+			("s" | "step", [] | [_]) => number(args.first(), 1).map(|n| self.step(n)),
+			("c" | "continue", []) => Ok(self.cont()),
+			("r" | "regs", []) => Ok(self.regs()),
+			("x", [at] | [at, _]) => number(args.get(1), 16)
+				.and_then(|len| self.dump(self.resolve(at)?, len)),
+			("l" | "list", []) => Ok(self.current()),
+			_ => Err(format!("unknown command `{}`, try `help`", line.trim())),
+		};
+
+		Some(result)
+	}
+}
+
+
+fn describe(stop: Stop) -> String {
+	match stop {
+		Stop::Halted => "program halted".to_string(),
+		Stop::StepLimit => "step limit reached".to_string(),
+		Stop::Fault { pc, fault } => format!("{fault} at {pc:#x}"),
+	}
+}
@@ -0,0 +1,143 @@
+use logos::Span;
+
+use crate::srcmap::SourceMap;
+
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Level {
+	Error,
+	Warning,
+}
+
+#[derive(Debug, Clone)]
+pub struct Diagnostic {
+	pub level: Level,
+	pub msg: String,
+	pub span: Option<Span>,
+}
+
+pub struct Source {
+	pub name: String,
+	pub text: String,
+	line_starts: Vec<usize>,
+	// origin of every line when the text was assembled from several files
+	pub map: Option<SourceMap>,
+}
+
+
+impl Diagnostic {
+	pub fn error<S: Into<String>>(span: Span, msg: S) -> Self {
+		Diagnostic {
+			level: Level::Error,
+			msg: msg.into(),
+			span: Some(span),
+		}
+	}
+
+	pub fn warning<S: Into<String>>(span: Span, msg: S) -> Self {
+		Diagnostic {
+			level: Level::Warning,
+			msg: msg.into(),
+			span: Some(span),
+		}
+	}
+
+	// for errors that are not tied to any source text (missing files, bad arguments)
+	pub fn global<S: Into<String>>(msg: S) -> Self {
+		Diagnostic {
+			level: Level::Error,
+			msg: msg.into(),
+			span: None,
+		}
+	}
+
+	pub fn render(&self, source: &Source) -> String {
+		let level = match self.level {
+			Level::Error => "error",
+			Level::Warning => "warning",
+		};
+
+		let mut result = format!("{level}: {}\n", self.msg);
+
+		let Some(span) = &self.span else {
+			return result;
+		};
+
+		let (line, col) = source.line_col(span.start);
+		let text = source.line_text(line);
+		let (name, line) = source.origin(line);
+		let number = line.to_string();
+		let pad = " ".repeat(number.len());
+
+		// underline up to the end of the first line of the span, at least one caret
+		let start = span.start.min(source.text.len());
+		let end = span.end.clamp(start, source.text.len());
+		let width = source.text[start..end]
+			.lines()
+			.next()
+			.map_or(0, |s| s.chars().count())
+			.max(1);
+
+		let prefix: String = text
+			.chars()
+			.take(col - 1)
+			.map(|c| if c == '\t' { '\t' } else { ' ' })
+			.collect();
+
+		result += &format!("{pad}--> {name}:{line}:{col}\n");
+		result += &format!("{pad} |\n");
+		result += &format!("{number} | {text}\n");
+		result += &format!("{pad} | {prefix}{}\n", "^".repeat(width));
+
+		result
+	}
+}
+
+
+impl Source {
+	pub fn new<N: Into<String>, T: Into<String>>(name: N, text: T) -> Self {
+		let text = text.into();
+		let line_starts = std::iter::once(0)
+			.chain(text.match_indices('\n').map(|(i, _)| i + 1))
+			.collect();
+
+		Source {
+			name: name.into(),
+			text,
+			line_starts,
+			map: None,
+		}
+	}
+
+	pub fn with_map(mut self, map: SourceMap) -> Self {
+		self.map = Some(map);
+		self
+	}
+
+	// file name and line in it that a line of the text came from
+	pub fn origin(&self, line: usize) -> (&str, usize) {
+		self.map.as_ref()
+			.and_then(|m| m.origin(line))
+			.unwrap_or((&self.name, line))
+	}
+
+	// 1-based line and column (in characters) of a byte offset
+	pub fn line_col(&self, offset: usize) -> (usize, usize) {
+		let offset = offset.min(self.text.len());
+		let line = self.line_starts.partition_point(|&s| s <= offset);
+		let start = self.line_starts[line - 1];
+		(line, self.text[start..offset].chars().count() + 1)
+	}
+
+	pub fn line_text(&self, line: usize) -> &str {
+		let start = self.line_starts[line - 1];
+		let end = self.line_starts.get(line).map_or(self.text.len(), |e| e - 1);
+		self.text[start..end].trim_end_matches('\r')
+	}
+
+	pub fn line_span(&self, line: usize) -> Span {
+		let line = line.clamp(1, self.line_starts.len());
+		let start = self.line_starts[line - 1];
+		start..start + self.line_text(line).len()
+	}
+}
@@ -0,0 +1,127 @@
+use crate::asm::{instr_size, mnemonic};
+use crate::expr::{InstrArgs, INSTRS};
+
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Instruction {
+	pub op: u8,
+	pub size: u8,
+	pub r1: u8,
+	pub r2: u8,
+	pub r3: u8,
+	pub num8: u8,
+	pub num64: i64,
+	// length of the encoding in bytes, 4 or 12
+	pub len: u64,
+}
+
+
+// decodes the layout written by Expr::to_bytes:
+// op, r1 | r2 << 4, r3 | num8 & 0xf0, num8 & 0x0f | size << 4 [, num64]
+pub fn decode(bytes: &[u8]) -> Option<Instruction> {
+	let header = bytes.get(0..4)?;
+
+	if header[0] > 0x26 || header[3] >> 4 > 3 {
+		return None;
+	}
+
+	let len = instr_size(header[0]);
+	let num64 = if len == 12 {
+		i64::from_le_bytes(bytes.get(4..12)?.try_into().unwrap())
+	} else {
+		0
+	};
+
+	Some(Instruction {
+		op: header[0],
+		size: header[3] >> 4,
+		r1: header[1] & 0xf,
+		r2: header[1] >> 4,
+		r3: header[2] & 0xf,
+		num8: (header[2] & 0xf0) | (header[3] & 0xf),
+		num64,
+		len,
+	})
+}
+
+
+fn register(n: u8) -> String {
+	match n {
+		14 => "sp".to_string(),
+		15 => "pc".to_string(),
+		_  => format!("r{n}"),
+	}
+}
+
+
+pub fn symbolize(address: u64, symbols: &[(u64, String)]) -> Option<&str> {
+	symbols.iter().find(|(a, _)| *a == address).map(|(_, name)| name.as_str())
+}
+
+
+pub fn format(instr: &Instruction, symbols: &[(u64, String)]) -> String {
+	let suffix = match instr.size {
+		0 => "B",
+		1 => "S",
+		2 => "I",
+		3 => "L",
+		_ => unreachable!(),
+	};
+
+	let args: Vec<String> = INSTRS[instr.op as usize].iter()
+		.take_while(|a| !matches!(a, InstrArgs::None))
+		.map(|a| match a {
+			InstrArgs::R1 => register(instr.r1),
+			InstrArgs::R2 => register(instr.r2),
+			InstrArgs::R3 => register(instr.r3),
+			InstrArgs::Num8 => format!("{:#x}", instr.num8),
+			InstrArgs::Num64 => match symbolize(instr.num64 as u64, symbols) {
+				Some(name) => name.to_string(),
+				None => format!("{:#x}", instr.num64),
+			},
+			InstrArgs::None => unreachable!(),
+		})
+		.collect();
+
+	let name = format!("{}{suffix}", mnemonic(instr.op));
+
+	if args.is_empty() {
+		name
+	} else {
+		format!("{name} {}", args.join(", "))
+	}
+}
+
+
+pub fn disassemble(image: &[u8], base: u64, symbols: &[(u64, String)]) -> String {
+	let mut out = String::new();
+	let mut offset = 0;
+
+	while offset < image.len() {
+		let address = base.wrapping_add(offset as u64);
+
+		for (_, name) in symbols.iter().filter(|(a, _)| *a == address) {
+			out += &format!("{name}:\n");
+		}
+
+		let (text, len) = match decode(&image[offset..]) {
+			Some(instr) => (format(&instr, symbols), instr.len as usize),
+			None => {
+				let len = (image.len() - offset).min(4);
+				let bytes: Vec<String> = image[offset..offset + len].iter()
+					.map(|b| format!("{b:#04x}"))
+					.collect();
+				(format!("db {}", bytes.join(", ")), len)
+			},
+		};
+
+		let hex: Vec<String> = image[offset..offset + len].iter()
+			.map(|b| format!("{b:02x}"))
+			.collect();
+
+		out += &format!("{address:016x}:  {:<35}  {text}\n", hex.join(" "));
+		offset += len;
+	}
+
+	out
+}
@@ -0,0 +1,309 @@
+use std::fmt;
+
+use crate::disasm::{decode, Instruction};
+
+
+pub const SP: u8 = 14;
+pub const PC: u8 = 15;
+
+// bits of the flags register, set by every arithmetic and logic instruction
+pub const FLAG_ZERO: u64 = 1;
+pub const FLAG_CARRY: u64 = 2;
+pub const FLAG_SIGN: u64 = 4;
+
+// memory is allocated up front, larger sizes are refused before trying
+pub const MAX_MEMORY: u64 = 1 << 32;
+
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Fault {
+	InvalidInstruction,
+	Memory { address: u64, size: u64 },
+	DivisionByZero,
+	// chst, chtp, setsyscall, utok and ktou in user mode
+	Privileged,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Stop {
+	// an instruction jumped to itself
+	Halted,
+	StepLimit,
+	Fault { pc: u64, fault: Fault },
+}
+
+
+// r0 always reads as zero. kernel and user mode have their own register
+// banks, so a syscall or chst switches pc and sp along with the mode
+pub struct Machine {
+	pub memory: Vec<u8>,
+	pub regs: [[u64; 16]; 2],
+	pub user: bool,
+	pub flags: u64,
+	// interrupt table used by iint, one 8-byte address per vector
+	pub table: u64,
+	// kernel entry point used by syscall
+	pub syscall: u64,
+	pub steps: u64,
+}
+
+
+impl fmt::Display for Fault {
+	fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+		match self {
+			Fault::InvalidInstruction => write!(f, "invalid instruction"),
+			Fault::Memory { address, size } => write!(f, "{size}-byte access at {address:#x} is outside of memory"),
+			Fault::DivisionByZero => write!(f, "division by zero"),
+			Fault::Privileged => write!(f, "privileged instruction in user mode"),
+		}
+	}
+}
+
+
+fn mask(size: u8) -> u64 {
+	match size {
+		0 => 0xff,
+		1 => 0xffff,
+		2 => 0xffff_ffff,
+		_ => u64::MAX,
+	}
+}
+
+
+fn sign_extend(value: u64, size: u8) -> i64 {
+	let bits = 64 - (mask(size).count_ones());
+	((value << bits) as i64) >> bits
+}
+
+
+impl Machine {
+	pub fn new(memory_size: usize) -> Self {
+		let mut m = Machine {
+			memory: vec![0; memory_size],
+			regs: [[0; 16]; 2],
+			user: false,
+			flags: 0,
+			table: 0,
+			syscall: 0,
+			steps: 0,
+		};
+		m.regs[0][SP as usize] = memory_size as u64;
+		m.regs[1][SP as usize] = memory_size as u64;
+		m
+	}
+
+	// copies a flat image to `base` and starts executing at its first byte
+	pub fn load(&mut self, image: &[u8], base: u64) -> Result<(), Fault> {
+		self.range(base, image.len() as u64)?;
+		self.memory[base as usize..base as usize + image.len()].copy_from_slice(image);
+		self.set_reg(PC, base);
+		Ok(())
+	}
+
+	pub fn reg(&self, n: u8) -> u64 {
+		if n == 0 {
+			0
+		} else {
+			self.regs[self.user as usize][n as usize]
+		}
+	}
+
+	pub fn set_reg(&mut self, n: u8, value: u64) {
+		if n != 0 {
+			self.regs[self.user as usize][n as usize] = value;
+		}
+	}
+
+	fn range(&self, address: u64, size: u64) -> Result<std::ops::Range<usize>, Fault> {
+		match address.checked_add(size) {
+			Some(end) if end <= self.memory.len() as u64 => Ok(address as usize..end as usize),
+			_ => Err(Fault::Memory { address, size }),
+		}
+	}
+
+	pub fn read(&self, address: u64, size: u8) -> Result<u64, Fault> {
+		let range = self.range(address, 1 << size)?;
+		let mut bytes = [0; 8];
+		bytes[..range.len()].copy_from_slice(&self.memory[range]);
+		Ok(u64::from_le_bytes(bytes))
+	}
+
+	pub fn write(&mut self, address: u64, size: u8, value: u64) -> Result<(), Fault> {
+		let range = self.range(address, 1 << size)?;
+		let len = range.len();
+		self.memory[range].copy_from_slice(&value.to_le_bytes()[..len]);
+		Ok(())
+	}
+
+	pub fn push(&mut self, size: u8, value: u64) -> Result<(), Fault> {
+		let sp = self.reg(SP).wrapping_sub(1 << size);
+		self.write(sp, size, value)?;
+		self.set_reg(SP, sp);
+		Ok(())
+	}
+
+	pub fn pop(&mut self, size: u8) -> Result<u64, Fault> {
+		let sp = self.reg(SP);
+		let value = self.read(sp, size)?;
+		self.set_reg(SP, sp.wrapping_add(1 << size));
+		Ok(value)
+	}
+
+	pub fn fetch(&self) -> Result<Instruction, Fault> {
+		let pc = self.reg(PC);
+		let bytes = &self.memory[self.range(pc, 4)?.start..];
+
+		match decode(bytes) {
+			Some(instr) if instr.size <= 3 => Ok(instr),
+			_ => Err(Fault::InvalidInstruction),
+		}
+	}
+
+	// computes `a op b` truncated to the operand size and updates the flags
+	fn alu(&mut self, op: u8, size: u8, a: u64, b: u64) -> Result<u64, Fault> {
+		let m = mask(size);
+		let (a, b) = (a & m, b & m);
+
+		let (result, carry) = match op {
+			0x2 | 0x6 => (a.wrapping_add(b), (a as u128 + b as u128) > m as u128),
+			0x3 | 0x7 => (a.wrapping_sub(b), b > a),
+			0x4 | 0x8 => (a.wrapping_mul(b), (a as u128 * b as u128) > m as u128),
+			0x5 | 0x9 => {
+				if b == 0 {
+					return Err(Fault::DivisionByZero);
+				}
+				(sign_extend(a, size).wrapping_div(sign_extend(b, size)) as u64, false)
+			},
+			0xd => (!a, false),
+			0xe | 0x13 => (a & b, false),
+			0xf | 0x14 => (a | b, false),
+			0x10 | 0x15 => (a ^ b, false),
+			0x11 | 0x16 => (a.checked_shl(b as u32).unwrap_or(0), false),
+			0x12 | 0x17 => (a.checked_shr(b as u32).unwrap_or(0), false),
+			_ => unreachable!(),
+		};
+
+		let result = result & m;
+		self.flags &= !(FLAG_ZERO | FLAG_CARRY | FLAG_SIGN);
+		if result == 0 {
+			self.flags |= FLAG_ZERO;
+		}
+		if carry {
+			self.flags |= FLAG_CARRY;
+		}
+		if sign_extend(result, size) < 0 {
+			self.flags |= FLAG_SIGN;
+		}
+
+		Ok(result)
+	}
+
+	// executes one instruction, pc reads as the address of the next one
+	pub fn execute(&mut self, i: &Instruction) -> Result<(), Fault> {
+		let privileged = matches!(i.op, 0x1d | 0x1f | 0x23 | 0x24 | 0x25);
+		if privileged && self.user {
+			return Err(Fault::Privileged);
+		}
+
+		self.set_reg(PC, self.reg(PC).wrapping_add(i.len));
+
+		let n = i.num64 as u64;
+		let s = i.size;
+
+		match i.op {
+			0x0 => self.write(self.reg(i.r2).wrapping_add(n), s, self.reg(i.r3))?,
+			0x1 => {
+				let value = self.read(self.reg(i.r2).wrapping_add(n), s)?;
+				self.set_reg(i.r1, value);
+			},
+			0x2..=0x5 | 0xd..=0x12 => {
+				let value = self.alu(i.op, s, self.reg(i.r2), self.reg(i.r3))?;
+				self.set_reg(i.r1, value);
+			},
+			0x6..=0x9 | 0x13..=0x17 => {
+				let value = self.alu(i.op, s, self.reg(i.r2), n)?;
+				self.set_reg(i.r1, value);
+			},
+			0xa..=0xc => {
+				let flag = match i.op {
+					0xa => FLAG_ZERO,
+					0xb => FLAG_CARRY,
+					_ => FLAG_SIGN,
+				};
+				if self.flags & flag != 0 {
+					self.set_reg(i.r1, self.reg(i.r2).wrapping_add(n) & mask(s));
+				}
+			},
+			0x18 => self.push(s, self.reg(i.r3))?,
+			0x19 => {
+				let value = self.pop(s)?;
+				self.set_reg(i.r1, value);
+			},
+			0x1a => {
+				self.push(3, self.reg(PC))?;
+				self.set_reg(PC, self.reg(i.r3));
+			},
+			0x1b => {
+				let target = self.read(self.table.wrapping_add(i.num8 as u64 * 8), 3)?;
+				self.push(3, self.flags)?;
+				self.push(3, self.reg(PC))?;
+				self.set_reg(PC, target);
+			},
+			0x1c => {
+				let pc = self.pop(3)?;
+				self.flags = self.pop(3)?;
+				self.set_reg(PC, pc);
+			},
+			0x1d => self.user = self.reg(i.r2) & 1 != 0,
+			0x1e => self.set_reg(i.r1, self.user as u64),
+			0x1f => self.table = self.reg(i.r2),
+			0x20 => self.set_reg(i.r1, self.table),
+			0x21 => self.flags = self.reg(i.r2),
+			0x22 => self.set_reg(i.r1, self.flags),
+			0x23 => {
+				let value = if i.r3 == 0 { 0 } else { self.regs[1][i.r3 as usize] };
+				self.set_reg(i.r1, value);
+			},
+			0x24 => {
+				if i.r1 != 0 {
+					self.regs[1][i.r1 as usize] = self.reg(i.r3);
+				}
+			},
+			0x25 => self.syscall = self.reg(i.r2),
+			0x26 => {
+				self.user = false;
+				self.set_reg(PC, self.syscall);
+			},
+			_ => unreachable!(),
+		}
+
+		Ok(())
+	}
+
+	pub fn step(&mut self) -> Option<Stop> {
+		let pc = self.reg(PC);
+
+		let result = self.fetch().and_then(|i| self.execute(&i));
+		self.steps += 1;
+
+		match result {
+			Err(fault) => {
+				// leave pc at the faulting instruction
+				self.set_reg(PC, pc);
+				Some(Stop::Fault { pc, fault })
+			},
+			Ok(()) if self.reg(PC) == pc => Some(Stop::Halted),
+			Ok(()) => None,
+		}
+	}
+
+	// runs until the program halts or faults, or `limit` instructions have executed
+	pub fn run(&mut self, limit: u64) -> Stop {
+		for _ in 0..limit {
+			if let Some(stop) = self.step() {
+				return stop;
+			}
+		}
+		Stop::StepLimit
+	}
+}
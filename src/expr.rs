@@ -1,5 +1,8 @@
 use logos::Span;
 
+use crate::diag::Diagnostic;
+use crate::asm::{mnemonic, directive_args};
+
 
 #[derive(Debug, Clone)]
 pub enum ExprKind<'a> {
@@ -11,9 +14,11 @@ pub enum ExprKind<'a> {
 	Label(&'a str),
 	IName(u8, u8),
 	DType(u8),
+	DName(&'static str),
 
 	Instruction(u8, u8, Vec<Expr<'a>>),
 	Data(u8, Vec<Expr<'a>>),
+	Directive(&'static str, Vec<Expr<'a>>),
 
 	Vals(Vec<Expr<'a>>),
 
@@ -28,6 +33,44 @@ pub enum ExprKind<'a> {
 	Not(Box<Expr<'a>>),
 	Lsh(Box<Expr<'a>>, Box<Expr<'a>>),
 	Rsh(Box<Expr<'a>>, Box<Expr<'a>>),
+	Eq(Box<Expr<'a>>, Box<Expr<'a>>),
+	Ne(Box<Expr<'a>>, Box<Expr<'a>>),
+	Lt(Box<Expr<'a>>, Box<Expr<'a>>),
+	Le(Box<Expr<'a>>, Box<Expr<'a>>),
+	Gt(Box<Expr<'a>>, Box<Expr<'a>>),
+	Ge(Box<Expr<'a>>, Box<Expr<'a>>),
+	Neg(Box<Expr<'a>>),
+	LNot(Box<Expr<'a>>),
+	LAnd(Box<Expr<'a>>, Box<Expr<'a>>),
+	LOr(Box<Expr<'a>>, Box<Expr<'a>>),
+	Cond(Box<Expr<'a>>, Box<Expr<'a>>, Box<Expr<'a>>),
+}
+
+// result of evaluating an expression: a plain number, an offset from the start
+// of a section (only in relocatable mode) or an offset from an external symbol
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Value<'a> {
+	Abs(i64),
+	Rel(usize, i64),
+	Ext(&'a str, i64),
+}
+
+#[derive(Debug, Clone)]
+pub struct Reloc<'a> {
+	pub offset: u64,
+	pub size: u8,
+	pub target: Value<'a>,
+}
+
+#[derive(Default, Clone)]
+pub struct Symbols<'a> {
+	pub labels: Vec<Expr<'a>>,
+	pub externs: Vec<&'a str>,
+	pub relocatable: bool,
+	// start address of every section in a flat image
+	pub bases: Vec<u64>,
+	// .equ and .set definitions in source order
+	pub constants: Vec<Expr<'a>>,
 }
 
 #[derive(Debug, Clone)]
@@ -36,9 +79,10 @@ pub struct Expr<'a> {
 	pub span: Span,
 	pub size: u64,
 	pub offset: u64,
+	pub section: usize,
 }
 
-enum InstrArgs {
+pub enum InstrArgs {
 	None,
 	R1,
 	R2,
@@ -51,7 +95,7 @@ enum InstrArgs {
 use InstrArgs::*;
 
 
-const INSTRS: [[InstrArgs; 3]; 39] = [
+pub const INSTRS: [[InstrArgs; 3]; 39] = [
 	[R3,    R2,   Num64], // sto
 	[R1,    R2,   Num64], // loa
 	[R1,    R2,   R3],    // add
@@ -94,19 +138,146 @@ const INSTRS: [[InstrArgs; 3]; 39] = [
 ];
 
 
-impl Expr<'_> {
-	pub fn update_offset(&mut self, offset: u64) {
+// name of the symbol set by an .equ or .set
+pub fn constant_name<'a>(e: &Expr<'a>) -> &'a str {
+	match &e.kind {
+		ExprKind::Directive(".equ" | ".set", args) => match args[0].kind {
+			ExprKind::Id(name) => name,
+			_ => unreachable!(),
+		},
+		_ => unreachable!(),
+	}
+}
+
+
+fn label_name<'a>(l: &Expr<'a>) -> &'a str {
+	let ExprKind::Label(name) = l.kind else {unreachable!()};
+	name
+}
+
+
+// `1:` can be defined any number of times and is referenced as `1b` or `1f`
+fn is_numeric(name: &str) -> bool {
+	!name.is_empty() && name.bytes().all(|b| b.is_ascii_digit())
+}
+
+
+// labels that are neither numeric nor start with a dot open a new scope
+// for the dot-prefixed labels that follow them
+fn is_scope(name: &str) -> bool {
+	!name.starts_with('.') && !is_numeric(name)
+}
+
+
+impl<'a> Symbols<'a> {
+	// the labels after the scope label at `index` (or the start) up to the next one
+	fn scope(&self, index: Option<usize>) -> &[Expr<'a>] {
+		let start = index.map_or(0, |i| i + 1);
+		let end = self.labels[start..].iter()
+			.position(|l| is_scope(label_name(l)))
+			.map_or(self.labels.len(), |i| start + i);
+		&self.labels[start..end]
+	}
+
+	// index of the scope label in effect at offset `at` of the source
+	fn scope_at(&self, at: usize) -> Option<usize> {
+		self.labels.iter().rposition(|l| l.span.start < at && is_scope(label_name(l)))
+	}
+
+	// the label `name` refers to at offset `at` of the source
+	pub fn label(&self, name: &str, at: usize) -> Option<&Expr<'a>> {
+		let named = |l: &&Expr| label_name(l) == name;
+
+		if name.starts_with(|c: char| c.is_ascii_digit()) {
+			let (number, direction) = name.split_at(name.len() - 1);
+			let named = |l: &&Expr| label_name(l) == number;
+			return match direction {
+				"b" => self.labels.iter().filter(named).rfind(|l| l.span.start < at),
+				"f" => self.labels.iter().filter(named).find(|l| l.span.start > at),
+				_ => Option::None,
+			};
+		}
+
+		if name.starts_with('.') {
+			return self.scope(self.scope_at(at)).iter().find(named);
+		}
+
+		if let Some(l) = self.labels.iter().find(named) {
+			return Some(l);
+		}
+
+		// `func.loop` names the `.loop` that follows `func`
+		let (owner, local) = name.split_at(name.rfind('.')?);
+		let index = self.labels.iter().position(|l| label_name(l) == owner && is_scope(owner))?;
+		self.scope(Some(index)).iter().find(|l| label_name(l) == local)
+	}
+
+	// the name a label is known by outside of its scope, None for numeric labels
+	pub fn full_name(&self, l: &Expr<'a>) -> Option<String> {
+		let name = label_name(l);
+		if is_numeric(name) {
+			Option::None
+		} else if name.starts_with('.') {
+			let owner = self.scope_at(l.span.start).map_or("", |i| label_name(&self.labels[i]));
+			Some(format!("{owner}{name}"))
+		} else {
+			Some(name.to_string())
+		}
+	}
+
+	// the definition that gives `name` its value at offset `at` of the source:
+	// the last one before it, or the first one for a forward reference
+	pub fn definition(&self, name: &str, at: usize) -> Option<&Expr<'a>> {
+		let mut defs = self.constants.iter().filter(|c| constant_name(c) == name);
+		let first = defs.clone().next();
+		defs.rfind(|c| c.span.end <= at).or(first)
+	}
+
+	// the value of a constant after its last definition
+	pub fn constant_value(&self, name: &str) -> Result<Value<'a>, Diagnostic> {
+		let Some(def) = self.definition(name, usize::MAX) else {unreachable!()};
+		let ExprKind::Directive(_, args) = &def.kind else {unreachable!()};
+		args[1].value(self)
+	}
+
+	// every constant once, in order of first definition
+	pub fn constant_names(&self) -> Vec<&'a str> {
+		let mut names = vec![];
+		for c in &self.constants {
+			let name = constant_name(c);
+			if !names.contains(&name) {
+				names.push(name);
+			}
+		}
+		names
+	}
+}
+
+
+// only i64::MIN / -1 fails other than a zero divisor
+fn divide(l: i64, r: i64, f: fn(i64, i64) -> Option<i64>) -> Result<i64, &'static str> {
+	match r {
+		0 => Err("division by zero"),
+		_ => f(l, r).ok_or("division overflows"),
+	}
+}
+
+
+impl<'a> Expr<'a> {
+	pub fn update_offset(&mut self, section: usize, offset: u64) {
 		self.offset = offset;
+		self.section = section;
 
 		match &mut self.kind {
 			ExprKind::Instruction(_, _, arr) => {
 				for i in arr {
-					i.update_offset(offset);
+					i.update_offset(section, offset);
 				}
 			},
-			ExprKind::Data(_, arr) => {
+			ExprKind::Data(_, arr) |
+			ExprKind::Directive(_, arr) => {
 				for i in arr {
-					i.update_offset(offset);
+					i.update_offset(section, offset);
 				}
 			},
 			ExprKind::Sum(lhs, rhs) |
@@ -118,56 +289,294 @@ impl Expr<'_> {
 			ExprKind::Or(lhs, rhs) |
 			ExprKind::Xor(lhs, rhs) |
 			ExprKind::Lsh(lhs, rhs) |
-			ExprKind::Rsh(lhs, rhs) => {
-				lhs.update_offset(offset);
-				rhs.update_offset(offset);
+			ExprKind::Rsh(lhs, rhs) |
+			ExprKind::Eq(lhs, rhs) |
+			ExprKind::Ne(lhs, rhs) |
+			ExprKind::Lt(lhs, rhs) |
+			ExprKind::Le(lhs, rhs) |
+			ExprKind::Gt(lhs, rhs) |
+			ExprKind::Ge(lhs, rhs) |
+			ExprKind::LAnd(lhs, rhs) |
+			ExprKind::LOr(lhs, rhs) => {
+				lhs.update_offset(section, offset);
+				rhs.update_offset(section, offset);
 			},
 
-			ExprKind::Not(c) => {
-				c.update_offset(offset);
+			ExprKind::Not(c) |
+			ExprKind::Neg(c) |
+			ExprKind::LNot(c) => {
+				c.update_offset(section, offset);
+			},
+			ExprKind::Cond(c, lhs, rhs) => {
+				c.update_offset(section, offset);
+				lhs.update_offset(section, offset);
+				rhs.update_offset(section, offset);
 			},
 			_ => {}
 		}
 	}
 
-	fn eval(&self, labels: &Vec<Expr>) -> i64 {
-		match &self.kind {
-			ExprKind::Number(n) => *n,
+	pub fn check(&self) -> Vec<Diagnostic> {
+		let (op, args) = match &self.kind {
+			ExprKind::Instruction(op, _, args) => (op, args),
+			ExprKind::Directive(name, args) => return self.check_directive(name, args),
+			_ => return vec![],
+		};
+
+		let name = mnemonic(*op);
+		let slots: Vec<&InstrArgs> = INSTRS[*op as usize]
+			.iter()
+			.take_while(|a| !matches!(a, None))
+			.collect();
+
+		if args.len() != slots.len() {
+			let plural = if slots.len() == 1 { "" } else { "s" };
+			return vec![Diagnostic::error(self.span.clone(), format!(
+				"`{name}` takes {} operand{plural}, found {}", slots.len(), args.len()
+			))];
+		}
+
+		let mut diags = vec![];
+
+		for (i, (arg, slot)) in args.iter().zip(slots).enumerate() {
+			match (slot, &arg.kind) {
+				(R1 | R2 | R3, ExprKind::Reg(n)) if *n > 15 => diags.push(Diagnostic::error(
+					arg.span.clone(), format!("register r{n} does not exist")
+				)),
+				(R1 | R2 | R3, ExprKind::Reg(_)) => {},
+				(R1 | R2 | R3, _) => diags.push(Diagnostic::error(
+					arg.span.clone(), format!("operand {} of `{name}` must be a register", i + 1)
+				)),
+				(Num8 | Num64, ExprKind::Reg(_)) => diags.push(Diagnostic::error(
+					arg.span.clone(), format!("operand {} of `{name}` must be an expression, not a register", i + 1)
+				)),
+				_ => {},
+			}
+		}
+
+		diags
+	}
+
+	fn check_directive(&self, name: &str, args: &[Expr]) -> Vec<Diagnostic> {
+		let (min, max) = directive_args(name);
+
+		if args.len() < min || args.len() > max {
+			let count = if min == max { min.to_string() } else { format!("{min} to {max}") };
+			let plural = if max == 1 { "" } else { "s" };
+			return vec![Diagnostic::error(self.span.clone(), format!(
+				"`{name}` takes {count} argument{plural}, found {}", args.len()
+			))];
+		}
+
+		let mut diags = vec![];
+
+		match name {
+			".global" | ".extern" | ".ifdef" | ".ifndef" => for i in args {
+				if !matches!(i.kind, ExprKind::Id(_)) {
+					diags.push(Diagnostic::error(i.span.clone(), format!("`{name}` expects symbol names")));
+				}
+			},
+			".equ" | ".set" if !matches!(args[0].kind, ExprKind::Id(_)) => {
+				diags.push(Diagnostic::error(args[0].span.clone(), "expected symbol name"));
+			},
+			".section" if !matches!(args[0].kind, ExprKind::Id(_)) => {
+				diags.push(Diagnostic::error(args[0].span.clone(), "expected section name"));
+			},
+			_ => {},
+		}
+
+		diags
+	}
+
+	pub fn value(&self, syms: &Symbols<'a>) -> Result<Value<'a>, Diagnostic> {
+		self.resolve(syms, &[])
+	}
+
+	// `chain` holds the definitions being resolved, to catch constants that
+	// depend on themselves
+	fn resolve(&self, syms: &Symbols<'a>, chain: &[usize]) -> Result<Value<'a>, Diagnostic> {
+		let position = |section: usize, offset: u64| if syms.relocatable {
+			Value::Rel(section, offset as i64)
+		} else {
+			Value::Abs((syms.bases.get(section).copied().unwrap_or(0) + offset) as i64)
+		};
+
+		let binary = |lhs: &Expr<'a>, rhs: &Expr<'a>, f: fn(i64, i64) -> Result<i64, &'static str>| {
+			match (lhs.resolve(syms, chain)?, rhs.resolve(syms, chain)?) {
+				(Value::Abs(l), Value::Abs(r)) => f(l, r)
+					.map(Value::Abs)
+					.map_err(|msg| Diagnostic::error(self.span.clone(), msg)),
+				_ => Err(Diagnostic::error(self.span.clone(), "expression is not relocatable")),
+			}
+		};
+
+		Ok(match &self.kind {
+			ExprKind::Number(n) => Value::Abs(*n),
 			ExprKind::Id(id) => {
 				if *id == "$" {
-					return self.offset as i64;
+					return Ok(position(self.section, self.offset));
 				}
 
-				for l in labels {
-					if let ExprKind::Label(name) = l.kind {
-						if name == *id {
-							return l.offset as i64;
-						}
-					} else {unreachable!()}
+				if let Some(l) = syms.label(id, self.span.start) {
+					return Ok(position(l.section, l.offset));
 				}
 
-				println!("label {id} not found");
-				unimplemented!()
-			},
-			ExprKind::Reg(n) => *n as i64,
-			ExprKind::Sum(lhs, rhs) => lhs.eval(labels) + rhs.eval(labels),
-			ExprKind::Sub(lhs, rhs) => lhs.eval(labels) - rhs.eval(labels),
-			ExprKind::Mul(lhs, rhs) => lhs.eval(labels) * rhs.eval(labels),
-			ExprKind::Div(lhs, rhs) => lhs.eval(labels) / rhs.eval(labels),
-			ExprKind::Mod(lhs, rhs) => lhs.eval(labels) % rhs.eval(labels),
-			ExprKind::And(lhs, rhs) => lhs.eval(labels) & rhs.eval(labels),
-			ExprKind::Or(lhs, rhs) => lhs.eval(labels) | rhs.eval(labels),
-			ExprKind::Xor(lhs, rhs) => lhs.eval(labels) ^ rhs.eval(labels),
-			ExprKind::Not(c) => !c.eval(labels),
-			ExprKind::Lsh(lhs, rhs) => lhs.eval(labels) << rhs.eval(labels),
-			ExprKind::Rsh(lhs, rhs) => lhs.eval(labels) >> rhs.eval(labels),
+				if let Some(def) = syms.definition(id, self.span.start) {
+					if chain.contains(&def.span.start) {
+						return Err(Diagnostic::error(self.span.clone(), format!("`{id}` is defined in terms of itself")));
+					}
+					let ExprKind::Directive(_, args) = &def.kind else {unreachable!()};
+					return args[1].resolve(syms, &[chain, &[def.span.start]].concat());
+				}
+
+				if syms.relocatable && syms.externs.contains(id) {
+					return Ok(Value::Ext(id, 0));
+				}
+
+				return Err(Diagnostic::error(self.span.clone(), format!("label `{id}` not found")));
+			},
+			ExprKind::Reg(n) => Value::Abs(*n as i64),
+			ExprKind::Sum(lhs, rhs) => match (lhs.resolve(syms, chain)?, rhs.resolve(syms, chain)?) {
+				(Value::Abs(l), Value::Abs(r)) => Value::Abs(l.wrapping_add(r)),
+				(Value::Rel(s, l), Value::Abs(r)) |
+				(Value::Abs(r), Value::Rel(s, l)) => Value::Rel(s, l.wrapping_add(r)),
+				(Value::Ext(s, l), Value::Abs(r)) |
+				(Value::Abs(r), Value::Ext(s, l)) => Value::Ext(s, l.wrapping_add(r)),
+				_ => return Err(Diagnostic::error(self.span.clone(), "expression is not relocatable")),
+			},
+			ExprKind::Sub(lhs, rhs) => match (lhs.resolve(syms, chain)?, rhs.resolve(syms, chain)?) {
+				(Value::Abs(l), Value::Abs(r)) => Value::Abs(l.wrapping_sub(r)),
+				(Value::Rel(a, l), Value::Rel(b, r)) if a == b => Value::Abs(l.wrapping_sub(r)),
+				(Value::Rel(s, l), Value::Abs(r)) => Value::Rel(s, l.wrapping_sub(r)),
+				(Value::Ext(s, l), Value::Abs(r)) => Value::Ext(s, l.wrapping_sub(r)),
+				(Value::Ext(a, l), Value::Ext(b, r)) if a == b => Value::Abs(l.wrapping_sub(r)),
+				_ => return Err(Diagnostic::error(self.span.clone(), "expression is not relocatable")),
+			},
+			ExprKind::Mul(lhs, rhs) => binary(lhs, rhs, |l, r| Ok(l.wrapping_mul(r)))?,
+			ExprKind::Div(lhs, rhs) => binary(lhs, rhs, |l, r| divide(l, r, i64::checked_div))?,
+			ExprKind::Mod(lhs, rhs) => binary(lhs, rhs, |l, r| divide(l, r, i64::checked_rem))?,
+			ExprKind::And(lhs, rhs) => binary(lhs, rhs, |l, r| Ok(l & r))?,
+			ExprKind::Or(lhs, rhs) => binary(lhs, rhs, |l, r| Ok(l | r))?,
+			ExprKind::Xor(lhs, rhs) => binary(lhs, rhs, |l, r| Ok(l ^ r))?,
+			ExprKind::Lsh(lhs, rhs) => binary(lhs, rhs, |l, r| Ok(l.wrapping_shl(r as u32)))?,
+			ExprKind::Rsh(lhs, rhs) => binary(lhs, rhs, |l, r| Ok(l.wrapping_shr(r as u32)))?,
+			ExprKind::Eq(lhs, rhs) => binary(lhs, rhs, |l, r| Ok((l == r) as i64))?,
+			ExprKind::Ne(lhs, rhs) => binary(lhs, rhs, |l, r| Ok((l != r) as i64))?,
+			ExprKind::Lt(lhs, rhs) => binary(lhs, rhs, |l, r| Ok((l < r) as i64))?,
+			ExprKind::Le(lhs, rhs) => binary(lhs, rhs, |l, r| Ok((l <= r) as i64))?,
+			ExprKind::Gt(lhs, rhs) => binary(lhs, rhs, |l, r| Ok((l > r) as i64))?,
+			ExprKind::Ge(lhs, rhs) => binary(lhs, rhs, |l, r| Ok((l >= r) as i64))?,
+			ExprKind::Not(c) => match c.resolve(syms, chain)? {
+				Value::Abs(v) => Value::Abs(!v),
+				_ => return Err(Diagnostic::error(self.span.clone(), "expression is not relocatable")),
+			},
+			ExprKind::Neg(c) => match c.resolve(syms, chain)? {
+				Value::Abs(v) => Value::Abs(v.wrapping_neg()),
+				_ => return Err(Diagnostic::error(self.span.clone(), "expression is not relocatable")),
+			},
+			ExprKind::LNot(c) => Value::Abs((c.truth(syms, chain)? == 0) as i64),
+			// the right side of && and || and the branch not taken by ?: are not
+			// evaluated, so they may refer to labels that do not exist
+			ExprKind::LAnd(lhs, rhs) => Value::Abs((lhs.truth(syms, chain)? != 0 && rhs.truth(syms, chain)? != 0) as i64),
+			ExprKind::LOr(lhs, rhs) => Value::Abs((lhs.truth(syms, chain)? != 0 || rhs.truth(syms, chain)? != 0) as i64),
+			ExprKind::Cond(c, lhs, rhs) => if c.truth(syms, chain)? != 0 {
+				lhs.resolve(syms, chain)?
+			} else {
+				rhs.resolve(syms, chain)?
+			},
 			_ => unreachable!()
+		})
+	}
+
+	// a value used as a condition, which has to be a plain number
+	fn truth(&self, syms: &Symbols<'a>, chain: &[usize]) -> Result<i64, Diagnostic> {
+		match self.resolve(syms, chain)? {
+			Value::Abs(v) => Ok(v),
+			_ => Err(Diagnostic::error(self.span.clone(), "expected a constant expression")),
+		}
+	}
+
+	pub fn eval(&self, syms: &Symbols<'a>) -> Result<i64, Diagnostic> {
+		match self.value(syms)? {
+			Value::Abs(v) => Ok(v),
+			_ => Err(Diagnostic::error(self.span.clone(), "expected a constant expression")),
+		}
+	}
+
+	fn byte(&self, syms: &Symbols<'a>) -> Result<u8, Diagnostic> {
+		let val = self.eval(syms)?;
+		if !(-128..=255).contains(&val) {
+			return Err(Diagnostic::error(
+				self.span.clone(),
+				format!("value {val} does not fit in 8 bits"),
+			));
+		}
+		Ok(val as u8)
+	}
+
+	// evaluates a value that goes into a field of the output, recording a relocation
+	// for it if it depends on a position or an external symbol
+	fn field(&self, syms: &Symbols<'a>, offset: u64, size: u8, relocs: &mut Vec<Reloc<'a>>) -> Result<i64, Diagnostic> {
+		match self.value(syms)? {
+			Value::Abs(v) => Ok(v),
+			target => {
+				relocs.push(Reloc{offset, size, target});
+				Ok(0)
+			},
 		}
 	}
 
-	pub fn to_bytes(&self, labels: &Vec<Expr>) -> Vec<u8> {
+	// whether all bytes of the item are zero, without building the space reserved by
+	// directives that can be as large as a whole section
+	pub fn is_zero(&self, syms: &Symbols<'a>, relocs: &mut Vec<Reloc<'a>>) -> Result<bool, Diagnostic> {
 		match &self.kind {
+			ExprKind::Directive(".org" | ".align" | ".space", args) => match args.get(1) {
+				Some(v) => Ok(self.size == 0 || v.byte(syms)? == 0),
+				Option::None => Ok(true),
+			},
+			ExprKind::Directive(".fill", args) => {
+				let size = match args.get(1) {
+					Some(v) => v.eval(syms)? as u64,
+					Option::None => 1,
+				};
+				let value = match args.get(2) {
+					Some(v) => v.eval(syms)?,
+					Option::None => 0,
+				};
+				Ok(self.size == 0 || (0..size).all(|j| value >> (j * 8) & 0xff == 0))
+			},
+			_ => Ok(self.to_bytes(syms, relocs)?.iter().all(|b| *b == 0)),
+		}
+	}
+
+	pub fn to_bytes(&self, syms: &Symbols<'a>, relocs: &mut Vec<Reloc<'a>>) -> Result<Vec<u8>, Diagnostic> {
+		Ok(match &self.kind {
 			ExprKind::Label(_) => {vec![]},
+			ExprKind::Directive(".org" | ".align" | ".space", args) => {
+				let fill = match args.get(1) {
+					Some(v) => v.byte(syms)?,
+					Option::None => 0,
+				};
+				vec![fill; self.size as usize]
+			},
+			ExprKind::Directive(".fill", args) => {
+				let size = match args.get(1) {
+					Some(v) => v.eval(syms)? as u64,
+					Option::None => 1,
+				};
+				let value = match args.get(2) {
+					Some(v) => v.eval(syms)?,
+					Option::None => 0,
+				};
+				let mut result: Vec<u8> = vec![];
+				for _ in 0..self.size / size {
+					for j in 0..size {
+						result.push((value >> (j * 8) & 0xff) as u8);
+					}
+				}
+				result
+			},
+			ExprKind::Directive(_, _) => {vec![]},
 			ExprKind::Instruction(op, size, args) => {
 				let mut r1: u8 = 0;
 				let mut r2: u8 = 0;
@@ -179,13 +588,13 @@ impl Expr<'_> {
 				for i in 0..3 {
 					match INSTRS[*op as usize][i] {
 						None => break,
-						R1 => r1 = args[i].eval(labels) as u8,
-						R2 => r2 = args[i].eval(labels) as u8,
-						R3 => r3 = args[i].eval(labels) as u8,
-						Num8 => num8 = args[i].eval(labels) as u8,
+						R1 => r1 = args[i].eval(syms)? as u8,
+						R2 => r2 = args[i].eval(syms)? as u8,
+						R3 => r3 = args[i].eval(syms)? as u8,
+						Num8 => num8 = args[i].byte(syms)?,
 						Num64 => {
 							put_num64 = true;
-							num64 = args[i].eval(labels)
+							num64 = args[i].field(syms, self.offset + 4, 8, relocs)?
 						},
 					}
 				}
@@ -193,13 +602,13 @@ impl Expr<'_> {
 				let mut result: Vec<u8> = vec![
 					*op,
 					r1 | (r2 << 4),
-					r3 | (num8 & 0x0f << 4),
-					(num8 & 0xf0 >> 4) | (size << 4),
+					r3 | (num8 & 0xf0),
+					(num8 & 0x0f) | (size << 4),
 				];
 
 				if put_num64 {
 					for i in 0..8 {
-						result.push((num64 >> i * 8 & 0xff) as u8);
+						result.push((num64 >> (i * 8) & 0xff) as u8);
 					}
 				}
 
@@ -207,16 +616,16 @@ impl Expr<'_> {
 			},
 			ExprKind::Data(size, vals) => {
 				let mut result: Vec<u8> = vec![];
-				for i in vals {
-					let val = i.eval(labels);
+				for (n, i) in vals.iter().enumerate() {
+					let val = i.field(syms, self.offset + (n as u64) * (*size as u64), *size, relocs)?;
 					for j in 0..*size {
-						result.push((val >> j * 8 & 0xff) as u8);
+						result.push((val >> (j * 8) & 0xff) as u8);
 					}
 				}
 				result
 			},
 			_ => unreachable!()
-		}
+		})
 	}
 }
 
@@ -228,6 +637,7 @@ impl Default for Expr<'_> {
 			span: 0..0,
 			size: 0,
 			offset: 0,
+			section: 0,
 		}
 	}
 }
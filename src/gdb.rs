@@ -0,0 +1,294 @@
+use std::io::{self, Read, Write};
+use std::net::{TcpListener, TcpStream};
+
+use crate::emu::{Fault, Machine, Stop, PC};
+
+
+// registers are described to gdb in register number order, r14 and r15 are
+// named by their asm::register aliases
+pub const TARGET_XML: &str = r#"<?xml version="1.0"?>
+<!DOCTYPE target SYSTEM "gdb-target.dtd">
+<target version="1.0">
+  <feature name="org.rust_as.core">
+    <reg name="r0" bitsize="64" type="int64"/>
+    <reg name="r1" bitsize="64" type="int64"/>
+    <reg name="r2" bitsize="64" type="int64"/>
+    <reg name="r3" bitsize="64" type="int64"/>
+    <reg name="r4" bitsize="64" type="int64"/>
+    <reg name="r5" bitsize="64" type="int64"/>
+    <reg name="r6" bitsize="64" type="int64"/>
+    <reg name="r7" bitsize="64" type="int64"/>
+    <reg name="r8" bitsize="64" type="int64"/>
+    <reg name="r9" bitsize="64" type="int64"/>
+    <reg name="r10" bitsize="64" type="int64"/>
+    <reg name="r11" bitsize="64" type="int64"/>
+    <reg name="r12" bitsize="64" type="int64"/>
+    <reg name="r13" bitsize="64" type="int64"/>
+    <reg name="sp" bitsize="64" type="data_ptr"/>
+    <reg name="pc" bitsize="64" type="code_ptr"/>
+  </feature>
+</target>
+"#;
+
+// how many instructions run between checks for an interrupt from gdb
+const POLL_INTERVAL: u64 = 10000;
+
+
+pub struct Stub {
+	pub machine: Machine,
+	pub breakpoints: Vec<u64>,
+}
+
+
+fn checksum(data: &[u8]) -> u8 {
+	data.iter().fold(0u8, |sum, b| sum.wrapping_add(*b))
+}
+
+
+fn hex(bytes: &[u8]) -> String {
+	bytes.iter().map(|b| format!("{b:02x}")).collect()
+}
+
+
+fn unhex(s: &str) -> Option<Vec<u8>> {
+	if !s.len().is_multiple_of(2) {
+		return None;
+	}
+	(0..s.len()).step_by(2)
+		.map(|i| u8::from_str_radix(s.get(i..i + 2)?, 16).ok())
+		.collect()
+}
+
+
+fn number(s: &str) -> Option<u64> {
+	u64::from_str_radix(s, 16).ok()
+}
+
+
+// the stop reply for the way execution ended
+fn stop_reply(stop: Option<Stop>) -> String {
+	match stop {
+		None | Some(Stop::StepLimit) => "S05".to_string(),
+		Some(Stop::Halted) => "W00".to_string(),
+		Some(Stop::Fault { fault: Fault::InvalidInstruction | Fault::Privileged, .. }) => "S04".to_string(),
+		Some(Stop::Fault { fault: Fault::DivisionByZero, .. }) => "S08".to_string(),
+		Some(Stop::Fault { fault: Fault::Memory { .. }, .. }) => "S0b".to_string(),
+	}
+}
+
+
+fn read_packet(stream: &mut TcpStream) -> io::Result<Option<String>> {
+	let mut byte = [0];
+
+	// a packet with a bad checksum is refused, the client sends it again
+	loop {
+		// skip acks and anything else before the start of a packet
+		loop {
+			if stream.read(&mut byte)? == 0 {
+				return Ok(None);
+			}
+			match byte[0] {
+				b'$' => break,
+				0x03 => return Ok(Some("\x03".to_string())),
+				_ => {},
+			}
+		}
+
+		let mut data = vec![];
+		loop {
+			if stream.read(&mut byte)? == 0 {
+				return Ok(None);
+			}
+			if byte[0] == b'#' {
+				break;
+			}
+			data.push(byte[0]);
+		}
+
+		let mut sum = [0; 2];
+		stream.read_exact(&mut sum)?;
+
+		let expected = std::str::from_utf8(&sum).ok().and_then(|s| u8::from_str_radix(s, 16).ok());
+		if expected == Some(checksum(&data)) {
+			stream.write_all(b"+")?;
+			return Ok(Some(String::from_utf8_lossy(&data).into_owned()));
+		}
+
+		stream.write_all(b"-")?;
+	}
+}
+
+
+fn write_packet(stream: &mut TcpStream, data: &str) -> io::Result<()> {
+	write!(stream, "${data}#{:02x}", checksum(data.as_bytes()))?;
+	stream.flush()
+}
+
+
+// true when gdb sent an interrupt (ctrl-c) while the target was running
+fn interrupted(stream: &mut TcpStream) -> bool {
+	let mut byte = [0];
+	let _ = stream.set_nonblocking(true);
+	let result = matches!(stream.read(&mut byte), Ok(1) if byte[0] == 0x03);
+	let _ = stream.set_nonblocking(false);
+	result
+}
+
+
+impl Stub {
+	pub fn new(machine: Machine) -> Self {
+		Stub { machine, breakpoints: vec![] }
+	}
+
+	fn registers(&self) -> String {
+		(0..16).map(|n| hex(&self.machine.reg(n).to_le_bytes())).collect()
+	}
+
+	fn read_memory(&self, args: &str) -> Option<String> {
+		let (address, len) = args.split_once(',')?;
+		let (address, len) = (number(address)?, number(len)?);
+
+		let bytes: Result<Vec<u8>, _> = (address..address.wrapping_add(len))
+			.map(|a| self.machine.read(a, 0).map(|b| b as u8))
+			.collect();
+		bytes.ok().map(|b| hex(&b))
+	}
+
+	fn write_memory(&mut self, args: &str) -> Option<()> {
+		let (location, data) = args.split_once(':')?;
+		let (address, len) = location.split_once(',')?;
+		let (address, len) = (number(address)?, number(len)?);
+		let data = unhex(data)?;
+
+		if data.len() as u64 != len {
+			return None;
+		}
+		for (a, b) in (address..).zip(data) {
+			self.machine.write(a, 0, b as u64).ok()?;
+		}
+		Some(())
+	}
+
+	fn step(&mut self) -> String {
+		stop_reply(self.machine.step())
+	}
+
+	fn cont(&mut self, stream: &mut TcpStream) -> String {
+		loop {
+			if let Some(stop) = self.machine.step() {
+				return stop_reply(Some(stop));
+			}
+			if self.breakpoints.contains(&self.machine.reg(PC)) {
+				return stop_reply(None);
+			}
+			if self.machine.steps.is_multiple_of(POLL_INTERVAL) && interrupted(stream) {
+				return "S02".to_string();
+			}
+		}
+	}
+
+	fn query(&self, packet: &str) -> String {
+		if packet.starts_with("qSupported") {
+			return "PacketSize=4000;qXfer:features:read+".to_string();
+		}
+
+		if let Some(args) = packet.strip_prefix("qXfer:features:read:target.xml:") {
+			let Some((offset, len)) = args.split_once(',') else {
+				return "E01".to_string();
+			};
+			let (Some(offset), Some(len)) = (number(offset), number(len)) else {
+				return "E01".to_string();
+			};
+
+			let offset = (offset as usize).min(TARGET_XML.len());
+			let end = offset.saturating_add(len as usize).min(TARGET_XML.len());
+			let more = if end < TARGET_XML.len() { "m" } else { "l" };
+			return format!("{more}{}", &TARGET_XML[offset..end]);
+		}
+
+		match packet {
+			"qAttached" => "1".to_string(),
+			"qC" => "QC1".to_string(),
+			"qfThreadInfo" => "m1".to_string(),
+			"qsThreadInfo" => "l".to_string(),
+			_ => String::new(),
+		}
+	}
+
+	// answers one packet, None means the connection should be closed
+	pub fn handle(&mut self, packet: &str, stream: &mut TcpStream) -> Option<String> {
+		let (cmd, args) = packet.split_at(packet.chars().next().map_or(0, |c| c.len_utf8()));
+		let error = || "E01".to_string();
+
+		let reply = match cmd {
+			"\x03" | "?" => "S05".to_string(),
+			"g" => self.registers(),
+			"G" => match unhex(args) {
+				Some(bytes) if bytes.len() == 16 * 8 => {
+					for (n, value) in bytes.chunks(8).enumerate() {
+						self.machine.set_reg(n as u8, u64::from_le_bytes(value.try_into().unwrap()));
+					}
+					"OK".to_string()
+				},
+				_ => error(),
+			},
+			"p" => match number(args) {
+				Some(n) if n < 16 => hex(&self.machine.reg(n as u8).to_le_bytes()),
+				_ => error(),
+			},
+			"P" => {
+				let value = args.split_once('=')
+					.and_then(|(n, v)| Some((number(n)?, unhex(v)?)));
+				match value {
+					Some((n, v)) if n < 16 && v.len() == 8 => {
+						self.machine.set_reg(n as u8, u64::from_le_bytes(v.try_into().unwrap()));
+						"OK".to_string()
+					},
+					_ => error(),
+				}
+			},
+			"m" => self.read_memory(args).unwrap_or_else(error),
+			"M" => self.write_memory(args).map_or_else(error, |_| "OK".to_string()),
+			"s" => self.step(),
+			"c" => self.cont(stream),
+			"Z" | "z" => {
+				let mut parts = args.split(',');
+				match (parts.next(), parts.next().and_then(number)) {
+					(Some("0"), Some(address)) => {
+						self.breakpoints.retain(|b| *b != address);
+						if cmd == "Z" {
+							self.breakpoints.push(address);
+						}
+						"OK".to_string()
+					},
+					_ => String::new(),
+				}
+			},
+			"H" => "OK".to_string(),
+			"T" => "OK".to_string(),
+			"q" => self.query(packet),
+			"D" => {
+				let _ = write_packet(stream, "OK");
+				return None;
+			},
+			"k" => return None,
+			_ => String::new(),
+		};
+
+		Some(reply)
+	}
+
+	pub fn serve(&mut self, listener: &TcpListener) -> io::Result<()> {
+		let (mut stream, _) = listener.accept()?;
+		stream.set_nodelay(true)?;
+
+		while let Some(packet) = read_packet(&mut stream)? {
+			match self.handle(&packet, &mut stream) {
+				Some(reply) => write_packet(&mut stream, &reply)?,
+				None => break,
+			}
+		}
+
+		Ok(())
+	}
+}
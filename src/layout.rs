@@ -0,0 +1,253 @@
+use crate::asm::get_size;
+use crate::diag::Diagnostic;
+use crate::expr::{Expr, ExprKind, Symbols, Value};
+
+
+#[derive(Debug, Clone)]
+pub struct Section<'a> {
+	pub name: &'a str,
+	pub size: u64,
+	pub align: u64,
+	// reserves space without any bytes in the output
+	pub bss: bool,
+	// virtual address set with .base
+	pub base: Option<u64>,
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct Placement {
+	pub address: u64,
+	pub file_offset: u64,
+}
+
+
+// sections are built in memory before they are written out, anything larger
+// is refused instead of being allocated
+pub const MAX_SECTION_SIZE: u64 = 1 << 32;
+
+
+fn is_bss(name: &str) -> bool {
+	name == ".bss" || name.starts_with(".bss.")
+}
+
+
+// the value of a size or position against the labels placed so far, `all` has
+// every label to tell one used before its place is known from a missing one
+fn placed_value<'a>(e: &Expr<'a>, placed: &Symbols<'a>, all: &Symbols<'a>) -> Result<Value<'a>, Diagnostic> {
+	e.value(placed).map_err(|err| {
+		match e.value(&Symbols { relocatable: placed.relocatable, ..all.clone() }) {
+			Ok(_) => Diagnostic::error(e.span.clone(), "sizes may only use labels defined before them"),
+			Err(_) => err,
+		}
+	})
+}
+
+
+// assigns every item a section and an offset inside it, sizes may use the
+// constants in `syms` and the labels placed before them, at the addresses
+// `syms.bases` gives their sections
+pub fn layout<'a>(items: &mut [Expr<'a>], syms: &Symbols<'a>) -> Result<Vec<Section<'a>>, Vec<Diagnostic>> {
+	let mut placed = Symbols { labels: vec![], ..syms.clone() };
+	let all = Symbols {
+		labels: items.iter().filter(|i| matches!(i.kind, ExprKind::Label(_))).cloned().collect(),
+		..syms.clone()
+	};
+	let mut sections = vec![Section {
+		name: ".text",
+		size: 0,
+		align: 4,
+		bss: false,
+		base: None,
+	}];
+	let mut current = 0;
+	let mut diags = vec![];
+
+	for i in items.iter_mut() {
+		i.size = get_size(i);
+
+		let switch = match &i.kind {
+			ExprKind::Directive(".section", args) => {
+				let ExprKind::Id(name) = args[0].kind else {unreachable!()};
+				Some((name, args.get(1)))
+			},
+			ExprKind::Directive(name @ (".text" | ".data" | ".bss"), _) => Some((*name, None)),
+			_ => None,
+		};
+
+		if let Some((name, align)) = switch {
+			current = sections.iter().position(|s| s.name == name).unwrap_or_else(|| {
+				sections.push(Section {
+					name,
+					size: 0,
+					align: 4,
+					bss: is_bss(name),
+					base: None,
+				});
+				sections.len() - 1
+			});
+
+			if let Some(align) = align {
+				match align.eval(syms) {
+					Ok(n) if n > 0 && (n as u64).is_power_of_two() => {
+						sections[current].align = sections[current].align.max(n as u64);
+					},
+					Ok(n) => diags.push(Diagnostic::error(
+						align.span.clone(), format!("alignment {n} is not a power of two")
+					)),
+					Err(e) => diags.push(e),
+				}
+			}
+		}
+
+		let section = &mut sections[current];
+		let mut start = section.size;
+
+		if let ExprKind::Instruction(..) = i.kind {
+			if section.bss {
+				diags.push(Diagnostic::error(
+					i.span.clone(), format!("instructions are not allowed in `{}`", section.name)
+				));
+			}
+			start = start.div_ceil(4) * 4;
+		}
+
+		i.update_offset(current, start);
+
+		if let ExprKind::Label(_) = i.kind {
+			placed.labels.push(i.clone());
+		}
+
+		let number = |e: &Expr, value, diags: &mut Vec<Diagnostic>| match value {
+			Ok(Value::Abs(n)) if n >= 0 => Some(n as u64),
+			Ok(Value::Abs(n)) => {
+				diags.push(Diagnostic::error(e.span.clone(), format!("expected a non-negative value, found {n}")));
+				None
+			},
+			Ok(_) => {
+				diags.push(Diagnostic::error(e.span.clone(), "expected a constant expression"));
+				None
+			},
+			Err(e) => {
+				diags.push(e);
+				None
+			},
+		};
+		let constant = |e: &Expr<'a>, diags: &mut Vec<Diagnostic>| number(e, placed_value(e, &placed, &all), diags);
+
+		match &i.kind {
+			// .org counts from the start of the section, whatever its address
+			ExprKind::Directive(".org", args) => if let Some(target) = number(&args[0], {
+				let offsets = Symbols { relocatable: true, labels: placed.labels.clone(), ..syms.clone() };
+				match placed_value(&args[0], &offsets, &all) {
+					Ok(Value::Rel(s, offset)) if s == current => Ok(Value::Abs(offset)),
+					v => v,
+				}
+			}, &mut diags) {
+				if target < start {
+					diags.push(Diagnostic::error(i.span.clone(), format!(
+						"`.org` cannot move the location counter backwards from {start:#x} to {target:#x}"
+					)));
+				} else {
+					i.size = target - start;
+				}
+			},
+			ExprKind::Directive(".align", args) => if let Some(n) = constant(&args[0], &mut diags) {
+				if n.is_power_of_two() {
+					i.size = start.div_ceil(n) * n - start;
+					section.align = section.align.max(n);
+				} else {
+					diags.push(Diagnostic::error(args[0].span.clone(), format!("alignment {n} is not a power of two")));
+				}
+			},
+			ExprKind::Directive(".base", args) => if let Some(base) = constant(&args[0], &mut diags) {
+				match section.base {
+					Some(old) if old != base => diags.push(Diagnostic::error(i.span.clone(), format!(
+						"base address of `{}` is already set to {old:#x}", section.name
+					))),
+					_ => section.base = Some(base),
+				}
+			},
+			ExprKind::Directive(".space", args) => if let Some(n) = constant(&args[0], &mut diags) {
+				i.size = n;
+			},
+			ExprKind::Directive(".fill", args) => {
+				let count = constant(&args[0], &mut diags);
+				let size = match args.get(1) {
+					Some(size) => constant(size, &mut diags),
+					None => Some(1),
+				};
+				match (count, size) {
+					(Some(count), Some(size @ 1..=8)) => match count.checked_mul(size) {
+						Some(n) => i.size = n,
+						None => diags.push(Diagnostic::error(i.span.clone(), format!(
+							"`.fill` of {count} values of {size} bytes does not fit in the address space"
+						))),
+					},
+					(_, Some(1..=8) | None) => {},
+					(_, Some(size)) => diags.push(Diagnostic::error(
+						args[1].span.clone(), format!("`.fill` size must be between 1 and 8, found {size}")
+					)),
+				}
+			},
+			_ => {},
+		}
+
+		let end = match start.checked_add(i.size) {
+			Some(end) if end <= MAX_SECTION_SIZE => end,
+			_ => {
+				diags.push(Diagnostic::error(i.span.clone(), format!(
+					"section `{}` grows beyond {MAX_SECTION_SIZE:#x} bytes", section.name
+				)));
+				i.size = 0;
+				start
+			},
+		};
+
+		// every item is padded to 4 bytes, only .org and .align place the counter exactly
+		section.size = if matches!(i.kind, ExprKind::Directive(".org" | ".align", _)) {
+			end
+		} else {
+			end.div_ceil(4) * 4
+		};
+	}
+
+	if diags.is_empty() {
+		Ok(sections)
+	} else {
+		Err(diags)
+	}
+}
+
+
+// places sections one after another in a flat image starting at `base`, sections
+// without data go last so that they take no space in the file. a section with
+// its own .base keeps its place in the file but gets the requested address
+pub fn place(sections: &[Section], base: u64) -> Result<Vec<Placement>, Diagnostic> {
+	let mut placement = vec![Placement { address: 0, file_offset: 0 }; sections.len()];
+	let mut address = base;
+	let mut file_offset: u64 = 0;
+
+	for bss in [false, true] {
+		for (n, s) in sections.iter().enumerate() {
+			if s.bss != bss {
+				continue;
+			}
+
+			let overflow = || Diagnostic::global(format!(
+				"section `{}` does not fit in the 64-bit address space", s.name
+			));
+			let align = |n: u64| n.checked_next_multiple_of(s.align).ok_or_else(overflow);
+
+			address = match s.base {
+				Some(base) => base,
+				None => align(address)?,
+			};
+			file_offset = align(file_offset)?;
+			placement[n] = Placement { address, file_offset };
+			address = address.checked_add(s.size).ok_or_else(overflow)?;
+			file_offset = file_offset.checked_add(s.size).ok_or_else(overflow)?;
+		}
+	}
+
+	Ok(placement)
+}
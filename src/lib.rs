@@ -0,0 +1,17 @@
+pub mod token;
+pub mod expr;
+pub mod parser;
+pub mod asm;
+pub mod minipre;
+pub mod diag;
+pub mod object;
+pub mod link;
+pub mod layout;
+pub mod listing;
+pub mod disasm;
+pub mod emu;
+pub mod debug;
+pub mod gdb;
+pub mod srcmap;
+pub mod macros;
+pub mod cond;
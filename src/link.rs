@@ -0,0 +1,166 @@
+use std::collections::HashMap;
+
+use crate::diag::Diagnostic;
+use crate::layout::MAX_SECTION_SIZE;
+use crate::object::{Object, Target};
+
+
+pub struct LinkOptions {
+	pub base: u64,
+	// explicit start addresses of output sections
+	pub section_starts: Vec<(String, u64)>,
+}
+
+pub struct Input {
+	pub name: String,
+	pub object: Object,
+}
+
+
+fn fits(value: i64, size: u8) -> bool {
+	if size >= 8 {
+		return true;
+	}
+	let bits = size as u32 * 8;
+	value >= -(1 << (bits - 1)) && value < (1 << bits)
+}
+
+
+pub fn link(inputs: &[Input], opts: &LinkOptions) -> Result<Vec<u8>, Vec<Diagnostic>> {
+	let mut diags = vec![];
+
+	// output sections in order of first appearance, sections without data go last
+	let mut names: Vec<&str> = vec![];
+	for bss in [false, true] {
+		for i in inputs {
+			for s in &i.object.sections {
+				if s.bss == bss && !names.contains(&s.name.as_str()) {
+					names.push(&s.name);
+				}
+			}
+		}
+	}
+
+	// address and file offset of every input section, indexed by [input][section]
+	let mut addrs: Vec<Vec<u64>> = inputs.iter()
+		.map(|i| vec![0; i.object.sections.len()])
+		.collect();
+	let mut offsets = addrs.clone();
+
+	// like layout::place, a start address moves the section but not its place in the image
+	let mut address = opts.base;
+	let mut file_offset: u64 = 0;
+	// end of the last section with data, sections without data go last
+	let mut end = 0;
+
+	for name in &names {
+		let overflow = || vec![Diagnostic::global(format!(
+			"section `{name}` does not fit in the 64-bit address space"
+		))];
+
+		if let Some((_, start)) = opts.section_starts.iter().find(|(n, _)| n == name) {
+			address = *start;
+		}
+
+		for (n, i) in inputs.iter().enumerate() {
+			for (m, s) in i.object.sections.iter().enumerate() {
+				if s.name == *name {
+					address = address.checked_next_multiple_of(s.align).ok_or_else(overflow)?;
+					file_offset = file_offset.checked_next_multiple_of(s.align).ok_or_else(overflow)?;
+					addrs[n][m] = address;
+					offsets[n][m] = file_offset;
+					address = address.checked_add(s.size).ok_or_else(overflow)?;
+					file_offset = file_offset.checked_add(s.size).ok_or_else(overflow)?;
+					if !s.bss {
+						end = file_offset;
+					}
+				}
+			}
+		}
+	}
+
+	if end > MAX_SECTION_SIZE {
+		return Err(vec![Diagnostic::global(format!("image grows beyond {MAX_SECTION_SIZE:#x} bytes"))]);
+	}
+
+	let mut globals: HashMap<&str, (usize, u64)> = HashMap::new();
+
+	for (n, i) in inputs.iter().enumerate() {
+		for s in &i.object.symbols {
+			let address = match (s.global, s.section) {
+				(true, Some(section)) => addrs[n][section].wrapping_add(s.value),
+				(true, None) if s.absolute => s.value,
+				_ => continue,
+			};
+
+			if let Some((other, _)) = globals.get(s.name.as_str()) {
+				diags.push(Diagnostic::global(format!(
+					"duplicate symbol `{}` defined in `{}` and `{}`", s.name, inputs[*other].name, i.name
+				)));
+				continue;
+			}
+
+			globals.insert(&s.name, (n, address));
+		}
+	}
+
+	let mut image = vec![0; end as usize];
+
+	for (n, i) in inputs.iter().enumerate() {
+		for (m, s) in i.object.sections.iter().enumerate() {
+			if s.bss {
+				continue;
+			}
+
+			let at = offsets[n][m] as usize;
+			image[at..at + s.data.len()].copy_from_slice(&s.data);
+
+			for r in &s.relocs {
+				if r.offset.checked_add(r.size as u64).is_none_or(|end| end > s.data.len() as u64) {
+					diags.push(Diagnostic::global(format!(
+						"relocation at {}+{:#x} in `{}` is out of bounds", s.name, r.offset, i.name
+					)));
+					continue;
+				}
+
+				let target = match r.target {
+					Target::Section(t) => addrs[n][t],
+					Target::Symbol(t) => {
+						let sym = &i.object.symbols[t];
+						if let Some(section) = sym.section {
+							addrs[n][section].wrapping_add(sym.value)
+						} else if sym.absolute {
+							sym.value
+						} else if let Some((_, addr)) = globals.get(sym.name.as_str()) {
+							*addr
+						} else {
+							diags.push(Diagnostic::global(format!(
+								"undefined symbol `{}` referenced in `{}`", sym.name, i.name
+							)));
+							continue;
+						}
+					},
+				};
+
+				let value = (target as i64).wrapping_add(r.addend);
+				if !fits(value, r.size) {
+					diags.push(Diagnostic::global(format!(
+						"relocation at {}+{:#x} in `{}`: value {value:#x} does not fit in {} bytes",
+						s.name, r.offset, i.name, r.size
+					)));
+				}
+
+				let field = at + r.offset as usize;
+				for j in 0..r.size as usize {
+					image[field + j] = (value >> (j * 8) & 0xff) as u8;
+				}
+			}
+		}
+	}
+
+	if diags.is_empty() {
+		Ok(image)
+	} else {
+		Err(diags)
+	}
+}
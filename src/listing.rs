@@ -0,0 +1,194 @@
+use std::fmt::Write;
+
+use crate::diag::Source;
+use crate::expr::{Expr, ExprKind, Symbols, Value};
+use crate::layout::Section;
+
+
+const BYTES_PER_LINE: usize = 8;
+
+
+fn address(e: &Expr, syms: &Symbols) -> u64 {
+	syms.bases.get(e.section).copied().unwrap_or(0) + e.offset
+}
+
+
+// the file and line an item was written on, traced back through includes and macros
+fn location(e: &Expr, source: &Source) -> String {
+	let (line, _) = source.line_col(e.span.start);
+	let (file, line) = source.origin(line);
+	format!("{file}:{line}")
+}
+
+
+// every item with its address, encoded bytes, source location and text; fields
+// that need a relocation are shown as zeros
+pub fn listing(items: &[Expr], sections: &[Section], syms: &Symbols, source: &Source) -> String {
+	let mut out = String::new();
+	let width = items.iter().map(|i| location(i, source).len()).max().unwrap_or(0);
+
+	for i in items {
+		let bytes = i.to_bytes(syms, &mut vec![]).unwrap_or_default();
+		let location = location(i, source);
+		let text = source.text[i.span.clone()].lines().next().unwrap_or("");
+
+		let mut chunks = bytes.chunks(BYTES_PER_LINE);
+		let first = chunks.next().unwrap_or(&[]);
+		let hex = |chunk: &[u8]| chunk.iter().map(|b| format!("{b:02x}")).collect::<Vec<_>>().join(" ");
+
+		let _ = writeln!(
+			out,
+			"{:<8} {:016x}  {:<23}  {location:<width$}  {text}",
+			sections[i.section].name,
+			address(i, syms),
+			hex(first),
+		);
+
+		for (n, chunk) in chunks.enumerate() {
+			let _ = writeln!(
+				out,
+				"{:<8} {:016x}  {}",
+				"",
+				address(i, syms) + ((n + 1) * BYTES_PER_LINE) as u64,
+				hex(chunk),
+			);
+		}
+	}
+
+	out
+}
+
+
+// one `address section name` line per label and constant, sorted by address.
+// constants that are plain numbers are in the `*ABS*` section, numeric labels
+// are left out
+pub fn symbol_map(sections: &[Section], syms: &Symbols) -> String {
+	let mut labels: Vec<(u64, &str, String)> = syms.labels.iter()
+		.filter_map(|l| Some((address(l, syms), sections[l.section].name, syms.full_name(l)?)))
+		.collect();
+
+	// a flat image resolves labels to plain addresses, constants are evaluated
+	// relocatably as well to find the section of the ones based on a label
+	let relocatable = Symbols { relocatable: true, ..syms.clone() };
+
+	for name in syms.constant_names() {
+		let value = match relocatable.constant_value(name) {
+			Ok(Value::Rel(section, offset)) => Ok(Value::Rel(section, offset)),
+			_ => syms.constant_value(name),
+		};
+		match value {
+			Ok(Value::Abs(v)) => labels.push((v as u64, "*ABS*", name.to_string())),
+			Ok(Value::Rel(section, offset)) => labels.push((
+				syms.bases.get(section).copied().unwrap_or(0) + offset as u64, sections[section].name, name.to_string(),
+			)),
+			_ => {},
+		}
+	}
+
+	labels.sort();
+
+	let mut out = String::new();
+	for (address, section, name) in labels {
+		let _ = writeln!(out, "{address:016x} {section} {name}");
+	}
+	out
+}
+
+
+// one `address file:line` line per instruction, with lines traced back
+// through includes
+pub fn debug_lines(items: &[Expr], syms: &Symbols, source: &Source) -> String {
+	let mut out = String::new();
+
+	for i in items {
+		if !matches!(i.kind, ExprKind::Instruction(..)) {
+			continue;
+		}
+
+		let _ = writeln!(out, "{:016x} {}", address(i, syms), location(i, source));
+	}
+
+	out
+}
+
+
+// reads a file written by symbol_map back into (address, name) pairs
+pub fn read_map(text: &str) -> Result<Vec<(u64, String)>, String> {
+	let mut symbols = vec![];
+
+	for (n, line) in text.lines().enumerate() {
+		if line.trim().is_empty() {
+			continue;
+		}
+
+		let mut parts = line.split_whitespace();
+		let (Some(address), Some(_), Some(name)) = (parts.next(), parts.next(), parts.next()) else {
+			return Err(format!("malformed map entry on line {}", n + 1));
+		};
+		let address = u64::from_str_radix(address, 16)
+			.map_err(|_| format!("invalid address on line {}", n + 1))?;
+
+		symbols.push((address, name.to_string()));
+	}
+
+	Ok(symbols)
+}
+
+
+// reads the first line of every item that produced bytes back from a file
+// written by listing, as (address, line, source text)
+pub fn read_listing(text: &str) -> Result<Vec<(u64, usize, String)>, String> {
+	let mut lines = vec![];
+
+	for (n, line) in text.lines().enumerate() {
+		let malformed = || format!("malformed listing entry on line {}", n + 1);
+
+		if line.starts_with(' ') || line.trim().is_empty() {
+			continue;
+		}
+
+		let (_, rest) = line.split_once(' ').ok_or_else(malformed)?;
+		let rest = rest.trim_start();
+		let address = rest.get(..16).ok_or_else(malformed)?;
+		let address = u64::from_str_radix(address, 16).map_err(|_| malformed())?;
+
+		// the fixed width bytes column after the address, then `file:line` padded
+		// to the widest location and the source
+		let hex = rest.get(18..41).ok_or_else(malformed)?;
+		let rest = rest.get(43..).ok_or_else(malformed)?;
+		let (location, source) = rest.split_once("  ").unwrap_or((rest, ""));
+
+		if hex.trim().is_empty() {
+			continue;
+		}
+
+		let (_, number) = location.rsplit_once(':').ok_or_else(malformed)?;
+		let number = number.parse().map_err(|_| malformed())?;
+		lines.push((address, number, source.trim_start().to_string()));
+	}
+
+	Ok(lines)
+}
+
+
+// reads a file written by debug_lines back into (address, file, line)
+pub fn read_debug_lines(text: &str) -> Result<Vec<(u64, String, usize)>, String> {
+	let mut lines = vec![];
+
+	for (n, line) in text.lines().enumerate() {
+		if line.trim().is_empty() {
+			continue;
+		}
+
+		let malformed = || format!("malformed debug line entry on line {}", n + 1);
+
+		let (address, location) = line.split_once(' ').ok_or_else(malformed)?;
+		let (file, number) = location.rsplit_once(':').ok_or_else(malformed)?;
+		let address = u64::from_str_radix(address, 16).map_err(|_| malformed())?;
+		let number = number.parse().map_err(|_| malformed())?;
+
+		lines.push((address, file.to_string(), number));
+	}
+
+	Ok(lines)
+}
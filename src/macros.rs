@@ -0,0 +1,357 @@
+use std::collections::HashMap;
+use std::ops::Range;
+
+use logos::Logos;
+
+use crate::diag::{Diagnostic, Source};
+use crate::expr::{Expr, ExprKind, Symbols};
+use crate::parser::{parse, token_value};
+use crate::token::Token;
+
+
+// how deeply macros may call each other before expansion is considered endless
+const MAX_DEPTH: usize = 64;
+
+
+struct Macro {
+	// names and default values
+	params: Vec<(String, Option<String>)>,
+	body: Vec<String>,
+	// the macro whose body holds this definition, it is defined again on every expansion
+	owner: Option<String>,
+}
+
+struct Expander<'s> {
+	source: &'s Source,
+	macros: HashMap<String, Macro>,
+	// number of expansions so far, substituted for \@
+	counter: usize,
+	// macros being expanded, innermost last
+	expanding: Vec<String>,
+	out: String,
+	lines: Vec<usize>,
+}
+
+
+// the statement part of a line: leading `label:` words and the first word after them
+fn split_line(line: &str) -> (&str, &str, &str) {
+	let code = line.split("//").next().unwrap_or("");
+	let mut rest = code.trim_start();
+
+	while let Some(word) = rest.split_whitespace().next().filter(|w| w.ends_with(':')) {
+		rest = rest[word.len()..].trim_start();
+	}
+
+	let labels = &code[..code.len() - rest.len()];
+	let word = rest.split_whitespace().next().unwrap_or("");
+	(labels, word, rest[word.len()..].trim())
+}
+
+
+fn is_ident(s: &str) -> bool {
+	s.starts_with(|c: char| c.is_ascii_alphabetic() || c == '_' || c == '.')
+		&& s.chars().all(|c| c.is_ascii_alphanumeric() || c == '_' || c == '.')
+}
+
+
+// splits call arguments at commas outside of parentheses
+fn split_args(s: &str) -> Vec<&str> {
+	if s.is_empty() {
+		return vec![];
+	}
+
+	let mut args = vec![];
+	let mut depth = 0;
+	let mut start = 0;
+
+	for (i, c) in s.char_indices() {
+		match c {
+			'(' => depth += 1,
+			')' => depth -= 1,
+			',' if depth == 0 => {
+				args.push(s[start..i].trim());
+				start = i + 1;
+			},
+			_ => {},
+		}
+	}
+
+	args.push(s[start..].trim());
+	args
+}
+
+
+// the value of a `.if` or `.elseif` line that only uses numbers, None when it
+// needs labels or constants, which are only known after expansion
+fn constant_condition(line: &str) -> Option<bool> {
+	let mut tokens = vec![];
+	for (t, span) in Token::lexer(line).spanned() {
+		let tok = t.ok().filter(|t| *t != Token::Id("$"))?;
+		tokens.push((tok.clone(), Expr{span, kind: token_value(tok), ..Default::default()}));
+	}
+
+	let (items, diags) = parse(line, tokens);
+	match &items[..] {
+		[Expr{kind: ExprKind::Directive(".if" | ".elseif", args), ..}] if diags.is_empty() => {
+			args[0].eval(&Symbols::default()).ok().map(|v| v != 0)
+		},
+		_ => None,
+	}
+}
+
+
+// the `.elseif` and `.else` lines of the `.if` block starting at `lines[0]` and
+// its `.endif`, None when the block does not end
+fn branches(lines: &[(String, usize)]) -> Option<(Vec<usize>, usize)> {
+	let mut depth = 0;
+	let mut heads = vec![0];
+
+	for (n, (line, _)) in lines.iter().enumerate().skip(1) {
+		match split_line(line).1 {
+			".if" | ".ifdef" | ".ifndef" => depth += 1,
+			".elseif" | ".else" if depth == 0 => heads.push(n),
+			".endif" if depth == 0 => return Some((heads, n)),
+			".endif" => depth -= 1,
+			_ => {},
+		}
+	}
+
+	None
+}
+
+
+// the lines of the branch taken by the `.if` block at `lines[0]` and the index
+// of its `.endif`, None when a condition is not known yet
+fn decide(lines: &[(String, usize)]) -> Option<(Option<Range<usize>>, usize)> {
+	let (heads, endif) = branches(lines)?;
+
+	for (n, &head) in heads.iter().enumerate() {
+		let line = &lines[head].0;
+		let taken = match split_line(line).1 {
+			".else" => true,
+			_ => constant_condition(line)?,
+		};
+		if taken {
+			let end = heads.get(n + 1).copied().unwrap_or(endif);
+			return Some((Some(head + 1..end), endif));
+		}
+	}
+
+	Some((None, endif))
+}
+
+
+// replaces \param, \@ and the separator \() in a body line
+fn substitute(line: &str, values: &HashMap<String, String>, counter: usize) -> String {
+	let mut out = String::new();
+	let mut rest = line;
+
+	while let Some(i) = rest.find('\\') {
+		out += &rest[..i];
+		rest = &rest[i + 1..];
+
+		if let Some(r) = rest.strip_prefix('@') {
+			out += &counter.to_string();
+			rest = r;
+		} else if let Some(r) = rest.strip_prefix("()") {
+			rest = r;
+		} else {
+			let end = rest.find(|c: char| !c.is_ascii_alphanumeric() && c != '_').unwrap_or(rest.len());
+			match values.get(&rest[..end]) {
+				Some(value) => {
+					out += value;
+					rest = &rest[end..];
+				},
+				None => out.push('\\'),
+			}
+		}
+	}
+
+	out + rest
+}
+
+
+impl Expander<'_> {
+	fn error<S: Into<String>>(&self, line: usize, msg: S) -> Diagnostic {
+		Diagnostic::error(self.source.line_span(line), msg)
+	}
+
+	fn emit(&mut self, text: &str, origin: usize) {
+		self.out += text;
+		self.out.push('\n');
+		self.lines.push(origin);
+	}
+
+	// `.macro name params` and the lines up to the matching `.endm`, returns the lines consumed
+	fn define(&mut self, lines: &[(String, usize)], origin: usize) -> Result<usize, Diagnostic> {
+		let (_, _, header) = split_line(&lines[0].0);
+		let mut words = header.split(|c: char| c == ',' || c.is_whitespace()).filter(|w| !w.is_empty());
+
+		let name = match words.next() {
+			Some(name) if is_ident(name) => name.to_string(),
+			_ => return Err(self.error(origin, "expected a macro name after `.macro`")),
+		};
+		let owner = self.expanding.last().cloned();
+		if let Some(m) = self.macros.get(&name) && (m.owner.is_none() || m.owner != owner) {
+			return Err(self.error(origin, format!("macro `{name}` is already defined")));
+		}
+
+		let mut params = vec![];
+		for w in words {
+			let (param, default) = match w.split_once('=') {
+				Some((param, default)) => (param, Some(default.to_string())),
+				None => (w, None),
+			};
+			if !is_ident(param) || params.iter().any(|(p, _)| p == param) {
+				return Err(self.error(origin, format!("invalid parameter `{param}` of macro `{name}`")));
+			}
+			params.push((param.to_string(), default));
+		}
+
+		let mut depth = 0;
+		for (n, (line, _)) in lines.iter().enumerate().skip(1) {
+			match split_line(line).1 {
+				".macro" => depth += 1,
+				".endm" if depth == 0 => {
+					let body = lines[1..n].iter().map(|(l, _)| l.clone()).collect();
+					self.macros.insert(name, Macro { params, body, owner });
+					return Ok(n + 1);
+				},
+				".endm" => depth -= 1,
+				_ => {},
+			}
+		}
+
+		Err(self.error(origin, format!("`.macro {name}` is missing its `.endm`")))
+	}
+
+	// binds call arguments to parameters, by position or as `param=value`
+	fn bind(&self, name: &str, args: &str, origin: usize) -> Result<HashMap<String, String>, Diagnostic> {
+		let m = &self.macros[name];
+		let mut values = HashMap::new();
+
+		for (n, arg) in split_args(args).into_iter().enumerate() {
+			let keyword = arg.split_once('=')
+				.filter(|(p, _)| m.params.iter().any(|(param, _)| param == p.trim()));
+
+			let (param, value) = match keyword {
+				Some((p, value)) => (p.trim(), value.trim()),
+				None => match m.params.get(n) {
+					Some((param, _)) => (param.as_str(), arg),
+					None => return Err(self.error(origin, format!(
+						"macro `{name}` takes {} arguments, found more", m.params.len()
+					))),
+				},
+			};
+
+			values.insert(param.to_string(), value.to_string());
+		}
+
+		for (param, default) in &m.params {
+			if values.contains_key(param) {
+				continue;
+			}
+			match default {
+				Some(default) => {
+					values.insert(param.clone(), default.clone());
+				},
+				None => return Err(self.error(origin, format!("missing value for parameter `{param}` of macro `{name}`"))),
+			}
+		}
+
+		Ok(values)
+	}
+
+	// returns true when an `.exitm` ended the current expansion
+	fn run(&mut self, lines: &[(String, usize)]) -> Result<bool, Diagnostic> {
+		let mut i = 0;
+		// `.if` blocks opened by these lines
+		let mut conditions = 0usize;
+
+		while i < lines.len() {
+			let (line, origin) = (&lines[i].0, lines[i].1);
+			let (labels, word, args) = split_line(line);
+
+			match word {
+				".macro" => {
+					i += self.define(&lines[i..], origin)?;
+					continue;
+				},
+				".endm" => return Err(self.error(origin, "`.endm` without `.macro`")),
+				".exitm" if self.expanding.is_empty() => {
+					return Err(self.error(origin, "`.exitm` outside of a macro"));
+				},
+				// the block is left to the assembler, so whether to leave is not known yet
+				".exitm" if conditions > 0 => {
+					return Err(self.error(origin, "`.exitm` inside a `.if` whose condition is only known after macro expansion"));
+				},
+				".exitm" => return Ok(true),
+				// a block that only depends on numbers, such as the arguments of a macro, is
+				// decided while expanding, so that it can hold `.exitm` or end a recursion
+				".if" if !self.expanding.is_empty() && let Some((taken, endif)) = decide(&lines[i..]) => {
+					if let Some(range) = taken && self.run(&lines[i..][range])? {
+						return Ok(true);
+					}
+					i += endif + 1;
+					continue;
+				},
+				".if" | ".ifdef" | ".ifndef" => {
+					conditions += 1;
+					self.emit(line, origin);
+				},
+				".endif" => {
+					conditions = conditions.saturating_sub(1);
+					self.emit(line, origin);
+				},
+				_ if self.macros.contains_key(word) => {
+					if self.expanding.len() >= MAX_DEPTH {
+						return Err(self.error(origin, format!("macro `{word}` is nested too deeply")));
+					}
+
+					if !labels.trim().is_empty() {
+						self.emit(labels.trim_end(), origin);
+					}
+
+					let values = self.bind(word, args, origin)?;
+					self.counter += 1;
+
+					let body: Vec<(String, usize)> = self.macros[word].body.iter()
+						.map(|l| (substitute(l, &values, self.counter), origin))
+						.collect();
+
+					// an `.exitm` leaves only the macro it is written in, the caller goes on
+					self.expanding.push(word.to_string());
+					self.run(&body)?;
+					self.expanding.pop();
+				},
+				_ => self.emit(line, origin),
+			}
+
+			i += 1;
+		}
+
+		Ok(false)
+	}
+}
+
+
+// expands `.macro` definitions and calls in a preprocessed text. returns the
+// new text and, for every line of it, the line of `source` it came from
+pub fn expand(source: &Source) -> Result<(String, Vec<usize>), Diagnostic> {
+	let lines: Vec<(String, usize)> = source.text.lines()
+		.enumerate()
+		.map(|(n, l)| (l.to_string(), n + 1))
+		.collect();
+
+	let mut expander = Expander {
+		source,
+		macros: HashMap::new(),
+		counter: 0,
+		expanding: vec![],
+		out: String::new(),
+		lines: vec![],
+	};
+
+	expander.run(&lines)?;
+	Ok((expander.out, expander.lines))
+}
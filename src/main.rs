@@ -1,184 +1,567 @@
 #![cfg_attr(debug_assertions, allow(dead_code, unused))]
 
-mod token;
-mod expr;
-mod parser;
-mod asm;
-mod minipre;
-
 use logos::Logos;
-use token::Token;
+use rust_as::token::{LexError, Token, parse_number};
+
+use rust_as::expr::{constant_name, Expr, ExprKind, Symbols, Value, Reloc};
+use rust_as::parser::{parse, token_value};
+use rust_as::layout::{self, layout, place};
+use rust_as::listing::{debug_lines, listing, symbol_map};
+use rust_as::object::{Object, Section, Symbol, Relocation, Target};
+
+use rust_as::minipre::{self, process_file, Context};
+use rust_as::diag::{Diagnostic, Level, Source};
+use rust_as::macros;
+use rust_as::cond::{is_conditional, select};
+
+use std::collections::HashSet;
+use std::path::PathBuf;
+
+
+// the file, line and message of a preprocessor error
+fn preprocessor_error(input: &str, e: minipre::Error) -> (Source, Diagnostic) {
+	let (file, e) = match e {
+		minipre::Error::Include { file, error } => (file, *error),
+		e => (input.to_string(), e),
+	};
+
+	let (line, msg) = match e {
+		minipre::Error::Syntax { line, msg } => (line, msg.to_string()),
+		minipre::Error::User { line, msg } => (line, msg),
+		minipre::Error::Included { line, msg } => (line, msg),
+		minipre::Error::Io(e) => return (Source::new("", ""), Diagnostic::global(format!("cannot read `{file}`: {e}"))),
+		e => return (Source::new("", ""), Diagnostic::global(e.to_string())),
+	};
+
+	let source = Source::new(file.as_str(), std::fs::read_to_string(&file).unwrap_or_default());
+	let span = source.line_span(line as usize);
+	(source, Diagnostic::error(span, msg))
+}
 
-use expr::{Expr, ExprKind};
-use parser::{reduce, Operation};
-use asm::{opcode, register, datatype, get_size};
 
-use minipre::{process_str, Context};
-use regex::Regex;
+// `#warning` messages, each rendered against its own file
+fn preprocessor_warnings(ctx: &Context) {
+	for (file, line, msg) in ctx.warnings() {
+		let source = Source::new(file.as_str(), std::fs::read_to_string(file).unwrap_or_default());
+		let span = source.line_span(*line as usize);
+		emit(&source, &[Diagnostic::warning(span, msg.as_str())]);
+	}
+}
 
-use std::path::Path;
 
+fn emit(source: &Source, diags: &[Diagnostic]) {
+	for d in diags {
+		eprint!("{}", d.render(source));
+	}
 
-fn token_value(tok: Token) -> ExprKind {
-	match tok {
-		Token::Number(n) => ExprKind::Number(n),
-		Token::Reg(n) => ExprKind::Reg(register(n)),
-		Token::Label(n) => ExprKind::Label(n),
-		Token::Id(n) => ExprKind::Id(n),
-		Token::IName(n) => {
-			if n.ends_with("B") {
-				ExprKind::IName(opcode(n.strip_suffix("B").unwrap()), 0)
-			}
-			else if n.ends_with("S")  {
-				ExprKind::IName(opcode(n.strip_suffix("S").unwrap()), 1)
-			}
-			else if n.ends_with("I") {
-				ExprKind::IName(opcode(n.strip_suffix("I").unwrap()), 2)
+	if diags.iter().any(|d| d.level == Level::Error) {
+		std::process::exit(1);
+	}
+}
+
+
+fn fail(source: &Source, diags: &[Diagnostic]) -> ! {
+	emit(source, diags);
+	std::process::exit(1);
+}
+
+
+fn write_file<C: AsRef<[u8]>>(source: &Source, name: &str, contents: C) {
+	if let Err(e) = std::fs::write(name, contents) {
+		fail(source, &[Diagnostic::global(format!("cannot write `{name}`: {e}"))]);
+	}
+}
+
+
+struct Options {
+	input: String,
+	output: String,
+	object: bool,
+	base: u64,
+	listing: Option<String>,
+	map: Option<String>,
+	debug: Option<String>,
+	include_dirs: Vec<PathBuf>,
+	// -D and -U in command line order, false for -U
+	defines: Vec<(String, bool)>,
+}
+
+
+fn parse_args() -> Result<Options, Diagnostic> {
+	let mut input = None;
+	let mut output = None;
+	let mut object = false;
+	let mut base = 0;
+	let mut listing = None;
+	let mut map = None;
+	let mut debug = None;
+	let mut include_dirs = vec![];
+	let mut defines = vec![];
+
+	let mut args = std::env::args().skip(1);
+
+	while let Some(arg) = args.next() {
+		let mut value = || args.next()
+			.ok_or(Diagnostic::global(format!("option `{arg}` expects a value")));
+
+		match arg.as_str() {
+			"-c" => object = true,
+			"--base" | "-Ttext" => {
+				let value = value()?;
+				base = parse_number(&value)
+					.ok_or(Diagnostic::global(format!("invalid address `{value}`")))? as u64;
+			},
+			"-l" => listing = Some(value()?),
+			"-M" => map = Some(value()?),
+			"-g" => debug = Some(value()?),
+			"-I" => include_dirs.push(PathBuf::from(value()?)),
+			_ if arg.starts_with("-I") => include_dirs.push(PathBuf::from(&arg[2..])),
+			"-D" => defines.push((value()?, true)),
+			_ if arg.starts_with("-D") => defines.push((arg[2..].to_string(), true)),
+			"-U" => defines.push((value()?, false)),
+			_ if arg.starts_with("-U") => defines.push((arg[2..].to_string(), false)),
+			_ if arg.starts_with('-') => return Err(Diagnostic::global(format!("unknown option `{arg}`"))),
+			_ if input.is_none() => input = Some(arg),
+			_ if output.is_none() => output = Some(arg),
+			_ => return Err(Diagnostic::global(format!("unexpected argument `{arg}`"))),
+		}
+	}
+
+	// debug lines hold plain addresses, which objects only get from the linker
+	if object && debug.is_some() {
+		return Err(Diagnostic::global("`-g` cannot be used with `-c`"));
+	}
+
+	Ok(Options {
+		input: input.ok_or(Diagnostic::global("expected input filename"))?,
+		output: output.ok_or(Diagnostic::global("expected output filename"))?,
+		object,
+		base,
+		listing,
+		map,
+		debug,
+		include_dirs,
+		defines,
+	})
+}
+
+
+// contents and relocations of every section
+type Contents<'a> = (Vec<Vec<u8>>, Vec<Vec<Reloc<'a>>>);
+
+
+// places each item at its offset inside its section
+fn assemble<'a>(
+	val_stack: &[Expr<'a>],
+	sections: &[layout::Section],
+	syms: &Symbols<'a>,
+) -> Result<Contents<'a>, Vec<Diagnostic>> {
+	let mut data: Vec<Vec<u8>> = vec![vec![]; sections.len()];
+	let mut relocs: Vec<Vec<Reloc>> = vec![vec![]; sections.len()];
+	let mut diags = vec![];
+
+	for i in val_stack {
+		let section = &sections[i.section];
+
+		if section.bss {
+			match i.is_zero(syms, &mut relocs[i.section]) {
+				Ok(true) => {},
+				Ok(false) => diags.push(Diagnostic::error(
+					i.span.clone(), format!("non-zero data in `{}`", section.name)
+				)),
+				Err(e) => diags.push(e),
 			}
-			else if n.ends_with("L") {
-				ExprKind::IName(opcode(n.strip_suffix("L").unwrap()), 3)
-			} else {
-				ExprKind::IName(opcode(n), 3)
+			continue;
+		}
+
+		match i.to_bytes(syms, &mut relocs[i.section]) {
+			Ok(bytes) => {
+				let out = &mut data[i.section];
+				out.resize(i.offset as usize, 0);
+				out.extend(bytes);
+			},
+			Err(e) => diags.push(e),
+		}
+	}
+
+	for (n, s) in sections.iter().enumerate() {
+		if !s.bss {
+			data[n].resize(s.size as usize, 0);
+		}
+	}
+
+	if diags.is_empty() {
+		Ok((data, relocs))
+	} else {
+		Err(diags)
+	}
+}
+
+
+fn build_object(val_stack: &[Expr], sections: &[layout::Section], syms: &Symbols) -> Result<Object, Vec<Diagnostic>> {
+	let mut diags = vec![];
+	let mut globals = vec![];
+
+	for i in val_stack {
+		if let ExprKind::Directive(".global", args) = &i.kind {
+			for a in args {
+				if let ExprKind::Id(name) = a.kind {
+					if syms.label(name, a.span.start).is_none() && !syms.constant_names().contains(&name) {
+						diags.push(Diagnostic::error(a.span.clone(), format!("global symbol `{name}` is not defined")));
+					}
+					globals.push(name);
+				}
 			}
+		}
+	}
+
+	let mut symbols: Vec<Symbol> = syms.labels.iter()
+		.filter_map(|l| {
+			let name = syms.full_name(l)?;
+			Some(Symbol {
+				global: globals.contains(&name.as_str()),
+				name,
+				section: Some(l.section),
+				value: l.offset,
+				absolute: false,
+			})
+		})
+		.collect();
+
+	// constants equal to an external symbol plus an offset have no ELF equivalent,
+	// references to them are relocated against the external symbol instead
+	for name in syms.constant_names() {
+		let (section, value, absolute) = match syms.constant_value(name) {
+			Ok(Value::Abs(v)) => (None, v as u64, true),
+			Ok(Value::Rel(section, offset)) => (Some(section), offset as u64, false),
+			_ => continue,
+		};
+		symbols.push(Symbol {
+			name: name.to_string(),
+			section,
+			value,
+			global: globals.contains(&name),
+			absolute,
+		});
+	}
+
+	let first_extern = symbols.len();
+	symbols.extend(syms.externs.iter().map(|name| Symbol {
+		name: name.to_string(),
+		section: None,
+		value: 0,
+		global: true,
+		absolute: false,
+	}));
+
+	let (data, relocs) = match assemble(val_stack, sections, syms) {
+		Ok(result) if diags.is_empty() => result,
+		Ok(_) => return Err(diags),
+		Err(errors) => {
+			diags.extend(errors);
+			return Err(diags);
 		},
-		Token::DataType(n) => ExprKind::DType(datatype(n)),
-		_ => ExprKind::None,
+	};
+
+	let sections = sections.iter().zip(data).zip(relocs)
+		.map(|((s, data), relocs)| Section {
+			name: s.name.to_string(),
+			data: if s.bss { vec![] } else { data },
+			size: s.size,
+			align: s.align,
+			bss: s.bss,
+			relocs: relocs.into_iter()
+				.map(|r| {
+					let (target, addend) = match r.target {
+						Value::Rel(section, addend) => (Target::Section(section), addend),
+						Value::Ext(name, addend) => {
+							let i = syms.externs.iter().position(|e| *e == name).unwrap();
+							(Target::Symbol(first_extern + i), addend)
+						},
+						Value::Abs(_) => unreachable!(),
+					};
+					Relocation { offset: r.offset, size: r.size, target, addend }
+				})
+				.collect(),
+		})
+		.collect();
+
+	Ok(Object {
+		sections,
+		symbols,
+	})
+}
+
+
+fn constants<'a>(items: &[Expr<'a>]) -> Vec<Expr<'a>> {
+	items.iter()
+		.filter(|e| matches!(e.kind, ExprKind::Directive(".equ" | ".set", _)))
+		.cloned()
+		.collect()
+}
+
+
+// labels other than numeric ones and names set with .equ cannot be defined
+// twice, and no constant may share its name with a label. every constant
+// definition is evaluated, even if it is never used
+fn check_symbols(syms: &Symbols) -> Vec<Diagnostic> {
+	let mut diags = vec![];
+	let mut names = HashSet::new();
+
+	for l in &syms.labels {
+		if let Some(name) = syms.full_name(l) && !names.insert(name.clone()) {
+			diags.push(Diagnostic::error(l.span.clone(), format!("label `{name}` is already defined")));
+		}
 	}
+
+	for (n, c) in syms.constants.iter().enumerate() {
+		let name = constant_name(c);
+		let ExprKind::Directive(kind, args) = &c.kind else {unreachable!()};
+
+		let fixed = syms.constants[..n].iter()
+			.filter(|d| constant_name(d) == name)
+			.any(|d| *kind == ".equ" || matches!(d.kind, ExprKind::Directive(".equ", _)));
+
+		if fixed || names.contains(name) {
+			diags.push(Diagnostic::error(args[0].span.clone(), format!("`{name}` is already defined")));
+		} else if let Err(e) = args[1].value(syms) {
+			diags.push(e);
+		}
+	}
+
+	diags
 }
 
 
-fn read_file(path: &Path) -> String {
-	let mut file = std::fs::read_to_string(path).expect(format!("file {} not found", path.to_str().unwrap()).as_str());
+fn symbols<'a>(items: &[Expr<'a>], sections: &[layout::Section], opts: &Options) -> Result<Symbols<'a>, Diagnostic> {
+	let mut externs = vec![];
+
+	for i in items {
+		if let ExprKind::Directive(".extern", args) = &i.kind {
+			for a in args {
+				if let ExprKind::Id(name) = a.kind {
+					externs.push(name);
+				}
+			}
+		}
+	}
+
+	Ok(Symbols {
+		labels: items.iter()
+			.filter(|e| matches!(e.kind, ExprKind::Label(_)))
+			.cloned()
+			.collect(),
+		externs,
+		relocatable: opts.object,
+		bases: if opts.object {
+			vec![0; sections.len()]
+		} else {
+			place(sections, opts.base)?.iter().map(|p| p.address).collect()
+		},
+		constants: constants(items),
+	})
+}
+
 
-	let regex = Regex::new("#include \".+\"").unwrap();
-	let name_regex = Regex::new("\".+\"").unwrap();
+// how many times the items are laid out before giving up on `.if` blocks
+// that keep changing which branch is taken
+const MAX_PASSES: usize = 16;
 
-	while let Some(c) = regex.captures(&file) {
-		let c = c.get(0).unwrap();
 
-		let include_filename = name_regex
-			.captures(c.as_str())
-			.unwrap()
-			.get(0)
-			.map_or("", |m| m.as_str().strip_prefix("\"").unwrap().strip_suffix("\"").unwrap());
+// lays out the items chosen by the `.if` blocks, then chooses again with the
+// resulting labels until the choice no longer changes. sizes may depend on the
+// addresses of sections, so the layout also has to reproduce the addresses it
+// was made with
+fn conditional_layout<'a>(
+	items: &mut [Expr<'a>],
+	opts: &Options,
+) -> Result<(Vec<Expr<'a>>, Vec<layout::Section<'a>>, Symbols<'a>), Vec<Diagnostic>> {
+	let mut syms = Symbols { relocatable: opts.object, bases: vec![opts.base], ..Default::default() };
+	let mut previous: Option<(Vec<bool>, Vec<Expr>, Vec<layout::Section>)> = None;
+	let mut settled = false;
 
-		let included_path = path.parent().unwrap_or(Path::new("/")).join(include_filename.to_string());
+	for _ in 0..MAX_PASSES {
+		let keep = select(items, &syms, false)?;
 
-		let included = read_file(&included_path);
+		if let Some((_, kept, sections)) = previous.take_if(|(last, _, _)| settled && *last == keep) {
+			select(items, &syms, true)?;
+			return Ok((kept, sections, syms));
+		}
 
-		let start = c.start();
-		let end = c.end();
+		let mut kept: Vec<Expr> = items.iter().zip(&keep)
+			.filter(|(_, k)| **k)
+			.map(|(i, _)| i.clone())
+			.collect();
+		let known = Symbols {
+			relocatable: opts.object,
+			bases: syms.bases.clone(),
+			constants: constants(&kept),
+			..Default::default()
+		};
+		let sections = layout(&mut kept, &known)?;
+
+		// conditions are evaluated on the original items, give them their new place
+		for (i, laid) in items.iter_mut().zip(&keep).filter(|(_, k)| **k).map(|(i, _)| i).zip(&kept) {
+			if is_conditional(i) {
+				*i = laid.clone();
+			}
+		}
 
-		file.drain(start..end);
-		file.insert_str(start, &included);
+		let next = symbols(&kept, &sections, opts).map_err(|e| vec![e])?;
+		settled = opts.object || next.bases == syms.bases;
+		syms = next;
+		previous = Some((keep, kept, sections));
 	}
 
-	file
+	let keep = select(items, &syms, false)?;
+	let last = previous.map(|(last, _, _)| last).unwrap_or_default();
+	let Some(changed) = keep.iter().zip(&last).position(|(a, b)| a != b) else {
+		return Err(vec![Diagnostic::global(format!(
+			"section addresses do not settle after {MAX_PASSES} passes"
+		))]);
+	};
+	let block = items[..=changed].iter().rposition(is_conditional).unwrap_or(changed);
+
+	Err(vec![Diagnostic::error(items[block].span.clone(), format!(
+		"conditional assembly does not settle after {MAX_PASSES} passes"
+	))])
 }
 
 
 fn main() {
 	let mut ctx = Context::new();
+	ctx.keep_lines(true);
+
+	let opts = match parse_args() {
+		Ok(opts) => opts,
+		Err(e) => fail(&Source::new("", ""), &[e]),
+	};
+	let input = opts.input.clone();
+
+	let version: Vec<u32> = env!("CARGO_PKG_VERSION").split('.').map(|n| n.parse().unwrap_or(0)).collect();
+	ctx.define("__RUST_AS__", "1")
+		.define("__RUST_AS_VERSION__", (version[0] * 10000 + version[1] * 100 + version[2]).to_string())
+		.define("__RUST_AS_TARGET__", "\"rust_as\"");
+
+	for (arg, define) in &opts.defines {
+		if *define {
+			ctx.define_arg(arg);
+		} else {
+			ctx.undefine(arg.as_str());
+		}
+	}
 
-	let inp = read_file(&Path::new(&std::env::args().nth(1).expect("expected input filename")));
-
-	let inp = process_str(
-		inp.as_str(),
-		&mut ctx
-	).unwrap();
-
-	dbg!(&inp);
+	for dir in &opts.include_dirs {
+		ctx.include_dir(dir);
+	}
+	if let Some(paths) = std::env::var_os("ASINCLUDE") {
+		for dir in std::env::split_paths(&paths) {
+			ctx.include_dir(dir);
+		}
+	}
 
-	let mut tokens = Token::lexer(&inp)
-		.spanned()
-		.map(|(t, s)| {
-			match t {
-				Ok(tok) => (tok.clone(), Expr{
+	let preprocessed = match process_file(&input, &mut ctx) {
+		Ok(text) => Source::new(input.as_str(), text).with_map(ctx.source_map().clone()),
+		Err(e) => {
+			preprocessor_warnings(&ctx);
+			let (source, diag) = preprocessor_error(&input, e);
+			fail(&source, &[diag]);
+		},
+	};
+
+	preprocessor_warnings(&ctx);
+
+	let source = match macros::expand(&preprocessed) {
+		Ok((text, lines)) => Source::new(input.as_str(), text).with_map(ctx.source_map().remap(&lines)),
+		Err(e) => fail(&preprocessed, &[e]),
+	};
+
+	let mut diags = vec![];
+	let mut tokens = Vec::new();
+
+	for (t, s) in Token::lexer(&source.text).spanned() {
+		match t {
+			Ok(tok) => tokens.push((tok.clone(), Expr{
+				span: s,
+				kind: token_value(tok),
+				..Default::default()
+			})),
+			Err(LexError::Unexpected) => {
+				let text = &source.text[s.clone()];
+				diags.push(Diagnostic::error(s, format!("unexpected character `{text}`")));
+			},
+			Err(LexError::Overflow) => {
+				// kept as a number so that the statement still parses
+				let text = &source.text[s.clone()];
+				diags.push(Diagnostic::error(s.clone(), format!("number `{text}` does not fit in 64 bits")));
+				tokens.push((Token::Number(0), Expr{
 					span: s,
-					kind: token_value(tok),
+					kind: ExprKind::Number(0),
 					..Default::default()
-				}),
-				Err(_) => unimplemented!()
-			}
-		})
-		.peekable();
-
-	let start = tokens.next().unwrap_or((
-		Token::EOI,
-		Expr{..Default::default()},
-	));
-
-	let mut stack: Vec<Token> = vec![start.0];
-	let mut val_stack: Vec<Expr> = vec![start.1];
-
-	loop {
-		// dbg!(&stack);
-		// dbg!(&val_stack);
-
-		match reduce(&stack, tokens.peek().unwrap_or(&(
-			Token::EOI,
-			Expr{..Default::default()},
-		)).0.clone()) {
-			Operation::NOMATCH => break,
-			Operation::SHIFT(expected) => {
-				let next = tokens.next().unwrap_or((
-					Token::EOI,
-					Expr{..Default::default()},
-				));
-				let mut find = false;
-				for i in expected {
-					if matches!(find, i) {
-						find = true;
-						break;
-					}
-				}
-				if !find {
-					unimplemented!();
-				}
-				stack.push(next.0);
-				val_stack.push(next.1);
-			},
-			Operation::REDUCE(n, action) => {
-				stack.drain(stack.len() - n..);
-				let (t, v) = action(
-					val_stack.drain(val_stack.len() - n..).collect(),
-				);
-				stack.push(t);
-				val_stack.push(v);
+				}));
 			},
 		}
 	}
 
-	// dbg!(&stack);
-	// dbg!(&val_stack);
+	let (mut val_stack, errors) = parse(&source.text, tokens);
+	diags.extend(errors);
 
-	for i in &mut val_stack {
-		i.size = get_size(i);
+	for i in &val_stack {
+		diags.extend(i.check());
 	}
 
-	let mut offset = 0;
-
-	for i in &mut val_stack {
-		i.update_offset(offset);
-		offset += i.size;
-		offset = (offset + 3) / 4 * 4;
+	if !diags.is_empty() {
+		fail(&source, &diags);
 	}
 
-	// dbg!(&val_stack);
+	let (val_stack, sections, syms) = match conditional_layout(&mut val_stack, &opts) {
+		Ok(result) => result,
+		Err(diags) => fail(&source, &diags),
+	};
 
-	let mut output: Vec<u8> = vec![];
-	let labels = val_stack.iter().cloned()
-		.filter(|e| matches!(e.kind, ExprKind::Label(_)))
-		.collect();
+	emit(&source, &check_symbols(&syms));
 
-	for i in val_stack {
-		let mut bytes = i.to_bytes(&labels);
-		for _ in 0..(bytes.len() + 3) / 4 * 4 - bytes.len() {
-			bytes.push(0);
+	let output = if opts.object {
+		let ignored = val_stack.iter().filter(|i| matches!(i.kind, ExprKind::Directive(".base", _)));
+		emit(&source, &ignored
+			.map(|i| Diagnostic::warning(i.span.clone(), "`.base` is ignored in object files, use `rust_ld --section-start`"))
+			.collect::<Vec<_>>());
+
+		match build_object(&val_stack, &sections, &syms) {
+			Ok(object) => object.write(),
+			Err(diags) => fail(&source, &diags),
+		}
+	} else {
+		let data = match assemble(&val_stack, &sections, &syms) {
+			Ok((data, _)) => data,
+			Err(diags) => fail(&source, &diags),
+		};
+
+		let mut output: Vec<u8> = vec![];
+
+		let placement = place(&sections, opts.base).unwrap_or_else(|e| fail(&source, &[e]));
+
+		for (s, (placement, data)) in sections.iter().zip(placement.iter().zip(data)) {
+			if !s.bss {
+				output.resize(placement.file_offset as usize, 0);
+				output.extend(data);
+			}
 		}
-		output.extend(bytes);
+
+		output
+	};
+
+	write_file(&source, &opts.output, output);
+
+	if let Some(name) = &opts.listing {
+		write_file(&source, name, listing(&val_stack, &sections, &syms, &source));
 	}
 
+	if let Some(name) = &opts.map {
+		write_file(&source, name, symbol_map(&sections, &syms));
+	}
 
-	let _ = std::fs::write(std::env::args().nth(2).expect("expected output filename"), output);
+	if let Some(name) = &opts.debug {
+		write_file(&source, name, debug_lines(&val_stack, &syms, &source));
+	}
 }
@@ -3,8 +3,9 @@
 
 //! # minipre
 //!
-//! minipre is a C-like generic preprocessor for Rust. It supports macros, #if, #elif, #else and
-//! #endif.
+//! minipre is a C-like generic preprocessor for Rust. It supports object-like and function-like
+//! macros, #undef, #include, #if, #elif, #else and #endif, and the builtin macros `__FILE__`,
+//! `__LINE__`, `__COUNTER__`, `__DATE__` and `__TIME__`.
 //!
 //! Process text with the `process` and `process_str` functions.
 //!
@@ -18,7 +19,7 @@
 //!     #endif
 //!     more FOO text";
 //!
-//! let result = minipre::process_str(text, minipre::Context::new().define("FOO", "1")).unwrap();
+//! let result = rust_as::minipre::process_str(text, rust_as::minipre::Context::new().define("FOO", "1")).unwrap();
 //!
 //! assert_eq!(result, "
 //!     some text
@@ -26,14 +27,17 @@
 //!     more 1 text");
 //! ```
 
-extern crate regex;
-
+use std::borrow::Cow;
+use std::cell::Cell;
 use std::collections::BTreeMap;
 use std::error;
 use std::fmt;
-use std::io::{self, BufRead, Write};
+use std::fs::File;
+use std::io::{self, BufRead, BufReader, Write};
+use std::path::{Path, PathBuf};
+use std::time::{SystemTime, UNIX_EPOCH};
 
-use regex::{Captures, Regex, Replacer};
+use crate::srcmap::SourceMap;
 
 /// The context for preprocessing a file.
 ///
@@ -42,24 +46,51 @@ use regex::{Captures, Regex, Replacer};
 /// # Example
 ///
 /// ```
-/// let mut context = minipre::Context::new();
+/// let mut context = rust_as::minipre::Context::new();
 /// context.define("my_macro", "5");
 /// assert_eq!(context.get_macro("my_macro").unwrap(), "5");
 /// ```
 #[derive(Debug, Clone)]
 pub struct Context {
-    defs: BTreeMap<String, String>,
+    defs: BTreeMap<String, Macro>,
+    keep_lines: bool,
+    include_dirs: Vec<PathBuf>,
+    // files that contained `#pragma once`
+    once: Vec<PathBuf>,
+    // files being processed with their index in `map`, innermost last
+    files: Vec<(PathBuf, usize)>,
+    // index in `map` for text that is not read from a file
+    input: Option<usize>,
+    map: SourceMap,
+    warnings: Vec<(String, u32, String)>,
+    // next value of __COUNTER__
+    counter: Cell<u64>,
+}
+
+#[derive(Debug, Clone)]
+struct Macro {
+    // None for object-like macros
+    params: Option<Vec<String>>,
+    body: String,
+}
+
+// a piece of a function-like macro body
+enum Piece<'a> {
+    Text(&'a str),
+    Param(usize),
+    Stringify(usize),
+    Paste,
 }
 
 /// Errors returned from preprocessing.
 ///
-/// minipre::Error inherits from fmt::Display and so can be very easily formatted and printed.
+/// rust_as::minipre::Error inherits from fmt::Display and so can be very easily formatted and printed.
 ///
 /// # Example
 ///
 /// ```
-/// let error = minipre::Error::Syntax { line: 16, msg: "Invalid character." };
-/// if let minipre::Error::Syntax { line, msg } = error {
+/// let error = rust_as::minipre::Error::Syntax { line: 16, msg: "Invalid character." };
+/// if let rust_as::minipre::Error::Syntax { line, msg } = error {
 ///     assert_eq!(line, 16);
 ///     assert_eq!(msg, "Invalid character.");
 /// } else {
@@ -72,13 +103,23 @@ pub enum Error {
     /// An error caused by malformed preprocessor syntax, with a line showing where the error
     /// occurred and a string explaining the error further.
     Syntax { line: u32, msg: &'static str },
+    /// An `#error` directive, with its line and message.
+    User { line: u32, msg: String },
+    /// An `#include` of a file that cannot be found or that is already being included, with
+    /// its line and a message naming the file and, for cycles, the chain of includes.
+    Included { line: u32, msg: String },
+    /// An error inside an included file, with the path of that file.
+    Include { file: String, error: Box<Error> },
 }
 
 impl fmt::Display for Error {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
         match self {
-            &Error::Io(ref e) => e.fmt(f),
-            &Error::Syntax { msg, line } => write!(f, "{} on line {}", msg, line),
+            Error::Io(e) => e.fmt(f),
+            Error::Syntax { msg, line } => write!(f, "{} on line {}", msg, line),
+            Error::User { msg, line } => write!(f, "{} on line {}", msg, line),
+            Error::Included { msg, line } => write!(f, "{} on line {}", msg, line),
+            Error::Include { file, error } => write!(f, "{}: {}", file, error),
         }
     }
 }
@@ -88,14 +129,18 @@ impl fmt::Display for Error {
 impl error::Error for Error {
     fn description(&self) -> &str {
         match self {
-            &Error::Io(ref e) => e.description(),
-            &Error::Syntax { msg, .. } => msg,
+            Error::Io(e) => e.description(),
+            Error::Syntax { msg, .. } => msg,
+            Error::User { msg, .. } => msg,
+            Error::Included { msg, .. } => msg,
+            Error::Include { error, .. } => error.description(),
         }
     }
     fn cause(&self) -> Option<&dyn error::Error> {
         match self {
-            &Error::Io(ref e) => Some(e),
-            &Error::Syntax { .. } => None,
+            Error::Io(e) => Some(e),
+            Error::Syntax { .. } | Error::User { .. } | Error::Included { .. } => None,
+            Error::Include { error, .. } => Some(error.as_ref()),
         }
     }
 }
@@ -106,102 +151,524 @@ impl From<io::Error> for Error {
     }
 }
 
+impl Default for Context {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
 impl Context {
     /// Creates a new, empty context with no macros defined.
     pub fn new() -> Self {
         Context {
             defs: BTreeMap::new(),
+            keep_lines: false,
+            include_dirs: Vec::new(),
+            once: Vec::new(),
+            files: Vec::new(),
+            input: None,
+            map: SourceMap::default(),
+            warnings: Vec::new(),
+            counter: Cell::new(0),
         }
     }
+    /// Makes processing replace directives and skipped lines with empty lines, so that every
+    /// output line is on the same line number as its input line.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// assert_eq!(rust_as::minipre::process_str("#if 0\nfoo\n#endif\nbar\n", rust_as::minipre::Context::new().keep_lines(true)).unwrap(), "\n\n\nbar\n");
+    /// ```
+    pub fn keep_lines(&mut self, keep: bool) -> &mut Self {
+        self.keep_lines = keep;
+        self
+    }
+    /// Adds a directory to search for `#include <file>`, and for `#include "file"` when the file
+    /// is not next to the including one. Directories are searched in the order they were added.
+    pub fn include_dir<P: Into<PathBuf>>(&mut self, dir: P) -> &mut Self {
+        self.include_dirs.push(dir.into());
+        self
+    }
+    /// Messages of the `#warning` directives met so far, with their file and line.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// let mut context = rust_as::minipre::Context::new();
+    /// rust_as::minipre::process_str("#if defined(FOO) || 2 * 3 > 5\n#warning check this\n#endif\n", &mut context).unwrap();
+    /// assert_eq!(context.warnings()[0].2, "check this");
+    /// ```
+    pub fn warnings(&self) -> &[(String, u32, String)] {
+        &self.warnings
+    }
+    /// The file and line that every line of the output came from.
+    pub fn source_map(&self) -> &SourceMap {
+        &self.map
+    }
+    fn find_include(&self, name: &str, quoted: bool) -> Option<PathBuf> {
+        let here = match self.files.last() {
+            Some((path, _)) => path.parent().unwrap_or(Path::new("/")).to_path_buf(),
+            None => PathBuf::from("."),
+        };
+
+        quoted
+            .then_some(here)
+            .into_iter()
+            .chain(self.include_dirs.iter().cloned())
+            .map(|dir| dir.join(name))
+            .find(|p| p.is_file())
+    }
+    fn record_line(&mut self, line: u32) {
+        let map = &mut self.map;
+        let file = match self.files.last() {
+            Some((_, id)) => *id,
+            None => *self.input.get_or_insert_with(|| map.add_file("<input>")),
+        };
+        self.map.push_line(file, line as usize);
+    }
     /// Defines a macro within a context. As this function returns &mut Self, it can be chained
     /// like in the example.
     ///
     /// # Example
     ///
     /// ```
-    /// assert_eq!(minipre::Context::new().define("foo", "bar").define("quaz", "quux").get_macro("foo").unwrap(), "bar");
+    /// assert_eq!(rust_as::minipre::Context::new().define("foo", "bar").define("quaz", "quux").get_macro("foo").unwrap(), "bar");
     /// ```
     pub fn define<N: Into<String>, V: Into<String>>(&mut self, name: N, value: V) -> &mut Self {
-        self.defs.insert(name.into(), value.into());
+        self.defs.insert(
+            name.into(),
+            Macro {
+                params: None,
+                body: value.into(),
+            },
+        );
+        self
+    }
+    /// Defines a function-like macro. Inside `body`, parameters are replaced by the arguments,
+    /// `#param` by the argument as a string literal, and `##` pastes its neighbours together.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// let mut context = rust_as::minipre::Context::new();
+    /// context.define_function("LOAD", &["r", "addr"], "loa r, r0, addr");
+    /// context.define_function("NAME", &["a", "b"], "#a a##b");
+    /// assert_eq!(rust_as::minipre::process_str("LOAD(r1, 8)\nNAME(x, 1)", &mut context).unwrap(), "loa r1, r0, 8\n\"x\" x1");
+    /// ```
+    pub fn define_function<N: Into<String>, P: AsRef<str>, V: Into<String>>(
+        &mut self,
+        name: N,
+        params: &[P],
+        body: V,
+    ) -> &mut Self {
+        self.defs.insert(
+            name.into(),
+            Macro {
+                params: Some(params.iter().map(|p| p.as_ref().to_string()).collect()),
+                body: body.into(),
+            },
+        );
+        self
+    }
+    /// Removes a macro, if it was defined.
+    pub fn undefine<N: Into<String>>(&mut self, name: N) -> &mut Self {
+        self.defs.remove(&name.into());
         self
     }
+    /// Defines a macro from a command line style `NAME` or `NAME=VALUE`, where `NAME` alone is
+    /// defined as 1.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// let mut context = rust_as::minipre::Context::new();
+    /// context.define_arg("DEBUG").define_arg("BOARD=3");
+    /// assert_eq!(context.get_macro("DEBUG").unwrap(), "1");
+    /// assert_eq!(context.get_macro("BOARD").unwrap(), "3");
+    /// ```
+    pub fn define_arg(&mut self, arg: &str) -> &mut Self {
+        match arg.split_once('=') {
+            Some((name, value)) => self.define(name, value),
+            None => self.define(arg, "1"),
+        }
+    }
     /// Gets a macro that may or may not be defined from a context.
     pub fn get_macro<N: Into<String>>(&self, name: N) -> Option<&String> {
-        self.defs.get(&name.into())
+        self.defs.get(&name.into()).map(|m| &m.body)
     }
-    fn build_regex(&self) -> Regex {
-        if self.defs.is_empty() {
-            Regex::new("$_").expect("Regex should be valid")
-        } else {
-            let pat: String = self
-                .defs
-                .keys()
-                .flat_map(|k| vec!["|", &k])
-                .skip(1)
-                .collect();
-            Regex::new(&format!("\\b(?:{})\\b", pat)).expect("Regex should be valid")
+    // parses the part of a `#define` line after the directive
+    fn parse_define(&mut self, expr: &str, line: u32) -> Result<(), Error> {
+        let end = ident_end(expr);
+        let name = &expr[..end];
+        if name.is_empty() || name.starts_with(|c: char| c.is_ascii_digit()) {
+            return Err(Error::Syntax {
+                line,
+                msg: "Expected macro name after `#define`",
+            });
         }
+
+        let rest = &expr[end..];
+        let Some(params) = rest.strip_prefix('(') else {
+            self.define(name, rest.split_whitespace().collect::<Vec<_>>().join(" "));
+            return Ok(());
+        };
+
+        let close = params.find(')').ok_or(Error::Syntax {
+            line,
+            msg: "Expected `)` after macro parameters",
+        })?;
+        let body = params[close + 1..].split_whitespace().collect::<Vec<_>>().join(" ");
+        let params: Vec<&str> = params[..close].split(',').map(|p| p.trim()).collect();
+        let params = if params == [""] { vec![] } else { params };
+
+        if params.iter().any(|p| p.is_empty() || ident_end(p) != p.len()) {
+            return Err(Error::Syntax {
+                line,
+                msg: "Invalid macro parameter",
+            });
+        }
+
+        self.define_function(name, &params, body);
+        Ok(())
     }
-    fn replacer<'a>(&'a self) -> impl Replacer + 'a {
-        move |captures: &Captures| {
-            self.defs
-                .get(captures.get(0).expect("At least one capture").as_str())
-                .expect("Found def for match")
-                .clone()
+    fn expand(&self, text: &str, line: u32) -> Result<String, Error> {
+        self.expand_with(text, &[], line)
+    }
+    // expands macros in `text`, except those in `disabled` that are being expanded already
+    fn expand_with(&self, text: &str, disabled: &[&str], line: u32) -> Result<String, Error> {
+        let mut out = String::new();
+        let mut rest = text;
+
+        while let Some(c) = rest.chars().next() {
+            let end = if c == '"' {
+                rest[1..].find('"').map_or(rest.len(), |i| i + 2)
+            } else if c.is_ascii_alphanumeric() || c == '_' {
+                ident_end(rest)
+            } else {
+                c.len_utf8()
+            };
+            let token = &rest[..end];
+            rest = &rest[end..];
+
+            // the operand of `defined` is a name, not something to expand
+            if token == "defined" {
+                let name = rest.trim_start();
+                let name = name.strip_prefix('(').unwrap_or(name).trim_start();
+                let skip = rest.len() - name.len() + ident_end(name);
+                out.push_str(token);
+                out.push_str(&rest[..skip]);
+                rest = &rest[skip..];
+                continue;
+            }
+
+            let m = match self.defs.get(token) {
+                Some(m) if !c.is_ascii_digit() && !disabled.contains(&token) => m,
+                None if is_builtin(token) => {
+                    out += &self.builtin(token, line);
+                    continue;
+                }
+                _ => {
+                    out.push_str(token);
+                    continue;
+                }
+            };
+
+            let inner = [disabled, &[token]].concat();
+            let Some(params) = &m.params else {
+                out += &self.expand_with(&m.body, &inner, line)?;
+                continue;
+            };
+
+            // a function-like macro name without arguments is left alone
+            let Some(after) = rest.trim_start().strip_prefix('(') else {
+                out.push_str(token);
+                continue;
+            };
+
+            let (args, remaining) = split_args(after, line)?;
+            rest = remaining;
+
+            let args = if params.is_empty() && args == [""] { vec![] } else { args };
+            if args.len() != params.len() {
+                return Err(Error::Syntax {
+                    line,
+                    msg: "Wrong number of macro arguments",
+                });
+            }
+
+            let body = self.substitute(m, params, &args, disabled, line)?;
+            out += &self.expand_with(&body, &inner, line)?;
+        }
+
+        Ok(out)
+    }
+    fn substitute(
+        &self,
+        m: &Macro,
+        params: &[String],
+        args: &[&str],
+        disabled: &[&str],
+        line: u32,
+    ) -> Result<String, Error> {
+        let param = |s: &str| params.iter().position(|p| p == s);
+
+        let mut pieces = Vec::new();
+        let mut rest = m.body.as_str();
+
+        while let Some(c) = rest.chars().next() {
+            if let Some(r) = rest.strip_prefix("##") {
+                pieces.push(Piece::Paste);
+                rest = r;
+                continue;
+            }
+
+            if let Some(r) = rest.strip_prefix('#') {
+                let r = r.trim_start();
+                let end = ident_end(r);
+                if let Some(n) = param(&r[..end]).filter(|_| end > 0) {
+                    pieces.push(Piece::Stringify(n));
+                    rest = &r[end..];
+                    continue;
+                }
+            }
+
+            let end = if c.is_ascii_alphanumeric() || c == '_' {
+                ident_end(rest)
+            } else {
+                c.len_utf8()
+            };
+            match param(&rest[..end]) {
+                Some(n) if !c.is_ascii_digit() => pieces.push(Piece::Param(n)),
+                _ => pieces.push(Piece::Text(&rest[..end])),
+            }
+            rest = &rest[end..];
+        }
+
+        let blank = |p: &Piece| matches!(p, Piece::Text(t) if t.trim().is_empty());
+        let next_to_paste = |i: usize| {
+            let before = pieces[..i].iter().rev().find(|p| !blank(p));
+            let after = pieces[i + 1..].iter().find(|p| !blank(p));
+            matches!(before, Some(Piece::Paste)) || matches!(after, Some(Piece::Paste))
+        };
+
+        let mut out = String::new();
+        let mut pasting = false;
+
+        for (i, piece) in pieces.iter().enumerate() {
+            match piece {
+                Piece::Paste => {
+                    out.truncate(out.trim_end().len());
+                    pasting = true;
+                    continue;
+                }
+                p if pasting && blank(p) => continue,
+                Piece::Text(t) => out.push_str(t),
+                // operands of ## are pasted as written, other arguments are expanded first
+                Piece::Param(n) if next_to_paste(i) => out.push_str(args[*n]),
+                Piece::Param(n) => out += &self.expand_with(args[*n], disabled, line)?,
+                Piece::Stringify(n) => {
+                    let escaped = args[*n].replace('\\', "\\\\").replace('"', "\\\"");
+                    out += &format!("\"{}\"", escaped);
+                }
+            }
+            pasting = false;
+        }
+
+        Ok(out)
+    }
+    // the value of a builtin macro that is not shadowed by a #define
+    fn builtin(&self, name: &str, line: u32) -> String {
+        match name {
+            "__FILE__" => {
+                let file = match self.files.last() {
+                    Some((path, _)) => path.display().to_string(),
+                    None => "<input>".to_string(),
+                };
+                format!("{:?}", file)
+            }
+            "__LINE__" => line.to_string(),
+            "__COUNTER__" => {
+                let n = self.counter.get();
+                self.counter.set(n + 1);
+                n.to_string()
+            }
+            "__DATE__" | "__TIME__" => {
+                let (date, time) = build_time();
+                format!("\"{}\"", if name == "__DATE__" { date } else { time })
+            }
+            _ => unreachable!(),
         }
     }
     fn skip_whitespace(&self, expr: &mut &str) {
         *expr = expr.trim_start();
     }
-    fn eval_term(&self, expr: &mut &str, line: u32) -> Result<bool, Error> {
+    // a number, `defined NAME`, `defined(NAME)`, a parenthesised expression or an identifier,
+    // which is 0 because every macro was already expanded
+    fn eval_term(&self, expr: &mut &str, line: u32, live: bool) -> Result<i64, Error> {
         self.skip_whitespace(expr);
 
-        let index = expr
-            .find(|c: char| !c.is_ascii_alphanumeric() && c != '_')
-            .unwrap_or(expr.len());
+        if let Some(rest) = expr.strip_prefix('(') {
+            *expr = rest;
+            let value = self.eval_ternary(expr, line, live)?;
+            self.skip_whitespace(expr);
+            *expr = expr.strip_prefix(')').ok_or(Error::Syntax {
+                line,
+                msg: "Expected `)`",
+            })?;
+            return Ok(value);
+        }
+
+        let index = ident_end(expr);
         let term = &expr[0..index];
         *expr = &expr[index..];
 
-        if term
-            .chars()
-            .next()
-            .ok_or_else(|| Error::Syntax {
+        let first = term.chars().next().ok_or(Error::Syntax {
+            line,
+            msg: "Expected term, found nothing",
+        })?;
+
+        if first.is_ascii_digit() {
+            let digits = term.trim_end_matches(['u', 'U', 'l', 'L']);
+            let value = if let Some(hex) = digits.strip_prefix("0x").or(digits.strip_prefix("0X")) {
+                i64::from_str_radix(hex, 16)
+            } else if let Some(bin) = digits.strip_prefix("0b").or(digits.strip_prefix("0B")) {
+                i64::from_str_radix(bin, 2)
+            } else if digits.len() > 1 && digits.starts_with('0') {
+                i64::from_str_radix(&digits[1..], 8)
+            } else {
+                digits.parse()
+            };
+            return value.map_err(|_| Error::Syntax {
                 line,
-                msg: "Expected term, found nothing",
-            })?
-            .is_digit(10)
-        {
-            Ok(term == "1")
+                msg: "Invalid number",
+            });
+        }
+
+        if term == "defined" {
+            self.skip_whitespace(expr);
+            let parens = expr.starts_with('(');
+            if parens {
+                *expr = expr[1..].trim_start();
+            }
+
+            let index = ident_end(expr);
+            let name = &expr[0..index];
+            *expr = &expr[index..];
+            if name.is_empty() {
+                return Err(Error::Syntax {
+                    line,
+                    msg: "Expected macro name after `defined`",
+                });
+            }
+
+            if parens {
+                self.skip_whitespace(expr);
+                *expr = expr.strip_prefix(')').ok_or(Error::Syntax {
+                    line,
+                    msg: "Expected `)` after `defined(`",
+                })?;
+            }
+            return Ok((self.defs.contains_key(name) || is_builtin(name)) as i64);
+        }
+
+        if first.is_ascii_alphabetic() || first == '_' {
+            Ok(0)
         } else {
             Err(Error::Syntax {
                 line,
-                msg: "Undefined identifier",
+                msg: "Unexpected character in expression",
             })
         }
     }
-    fn eval_unary(&self, expr: &mut &str, line: u32) -> Result<bool, Error> {
-        let mut negate = false;
+    fn eval_unary(&self, expr: &mut &str, line: u32, live: bool) -> Result<i64, Error> {
         self.skip_whitespace(expr);
-        while expr.starts_with("!") {
-            *expr = &expr[1..];
-            negate = !negate;
-            self.skip_whitespace(expr);
-        }
 
-        Ok(negate ^ self.eval_term(expr, line)?)
+        let op = expr.chars().next();
+        match op {
+            Some('!' | '~' | '-' | '+') => {
+                *expr = &expr[1..];
+                let value = self.eval_unary(expr, line, live)?;
+                Ok(match op {
+                    Some('!') => (value == 0) as i64,
+                    Some('~') => !value,
+                    Some('-') => value.wrapping_neg(),
+                    _ => value,
+                })
+            }
+            _ => self.eval_term(expr, line, live),
+        }
     }
-    fn eval_eq(&self, expr: &mut &str, line: u32) -> Result<bool, Error> {
-        let mut result = self.eval_unary(expr, line)?;
-        self.skip_whitespace(expr);
-        while expr.starts_with("==") {
-            *expr = &expr[2..];
-            result ^= !self.eval_unary(expr, line)?;
+    // binary operators by precedence climbing, `live` is false on the side of && and || that
+    // is not evaluated, where division by zero is not an error
+    fn eval_binary(&self, expr: &mut &str, min: u8, line: u32, live: bool) -> Result<i64, Error> {
+        let mut lhs = self.eval_unary(expr, line, live)?;
+
+        loop {
             self.skip_whitespace(expr);
+            let Some((op, prec)) = binary_op(expr).filter(|(_, prec)| *prec >= min) else {
+                return Ok(lhs);
+            };
+            *expr = &expr[op.len()..];
+
+            let rhs_live = match op {
+                "&&" => live && lhs != 0,
+                "||" => live && lhs == 0,
+                _ => live,
+            };
+            let rhs = self.eval_binary(expr, prec + 1, line, rhs_live)?;
+
+            lhs = match op {
+                "||" => (lhs != 0 || rhs != 0) as i64,
+                "&&" => (lhs != 0 && rhs != 0) as i64,
+                "|" => lhs | rhs,
+                "^" => lhs ^ rhs,
+                "&" => lhs & rhs,
+                "==" => (lhs == rhs) as i64,
+                "!=" => (lhs != rhs) as i64,
+                "<" => (lhs < rhs) as i64,
+                "<=" => (lhs <= rhs) as i64,
+                ">" => (lhs > rhs) as i64,
+                ">=" => (lhs >= rhs) as i64,
+                "<<" => lhs.wrapping_shl(rhs as u32),
+                ">>" => lhs.wrapping_shr(rhs as u32),
+                "+" => lhs.wrapping_add(rhs),
+                "-" => lhs.wrapping_sub(rhs),
+                "*" => lhs.wrapping_mul(rhs),
+                "/" | "%" if rhs == 0 && live => {
+                    return Err(Error::Syntax {
+                        line,
+                        msg: "Division by zero",
+                    })
+                }
+                "/" | "%" if rhs == 0 => 0,
+                "/" => lhs.wrapping_div(rhs),
+                "%" => lhs.wrapping_rem(rhs),
+                _ => unreachable!(),
+            };
         }
-        Ok(result)
+    }
+    fn eval_ternary(&self, expr: &mut &str, line: u32, live: bool) -> Result<i64, Error> {
+        let cond = self.eval_binary(expr, 1, line, live)?;
+        self.skip_whitespace(expr);
+
+        let Some(rest) = expr.strip_prefix('?') else {
+            return Ok(cond);
+        };
+        *expr = rest;
+
+        let then = self.eval_ternary(expr, line, live && cond != 0)?;
+        self.skip_whitespace(expr);
+        *expr = expr.strip_prefix(':').ok_or(Error::Syntax {
+            line,
+            msg: "Expected `:` in conditional expression",
+        })?;
+        let otherwise = self.eval_ternary(expr, line, live && cond == 0)?;
+
+        Ok(if cond != 0 { then } else { otherwise })
     }
     fn evaluate(&self, mut expr: &str, line: u32) -> Result<bool, Error> {
-        let result = self.eval_eq(&mut expr, line)?;
+        let result = self.eval_ternary(&mut expr, line, true)?;
         self.skip_whitespace(&mut expr);
         if !expr.is_empty() {
             return Err(Error::Syntax {
@@ -209,10 +676,112 @@ impl Context {
                 msg: "Expected end-of-line",
             });
         }
-        Ok(result)
+        Ok(result != 0)
     }
 }
 
+// the binary operator at the start of `s` and its precedence, higher binds tighter
+fn binary_op(s: &str) -> Option<(&'static str, u8)> {
+    const OPS: [(&str, u8); 18] = [
+        ("||", 1),
+        ("&&", 2),
+        ("==", 6),
+        ("!=", 6),
+        ("<=", 7),
+        (">=", 7),
+        ("<<", 8),
+        (">>", 8),
+        ("|", 3),
+        ("^", 4),
+        ("&", 5),
+        ("<", 7),
+        (">", 7),
+        ("+", 9),
+        ("-", 9),
+        ("*", 10),
+        ("/", 10),
+        ("%", 10),
+    ];
+    OPS.iter().find(|(op, _)| s.starts_with(op)).copied()
+}
+
+fn is_builtin(name: &str) -> bool {
+    matches!(
+        name,
+        "__FILE__" | "__LINE__" | "__COUNTER__" | "__DATE__" | "__TIME__"
+    )
+}
+
+// `Mmm dd yyyy` and `hh:mm:ss` in UTC, from SOURCE_DATE_EPOCH when it is set so that builds
+// can be reproduced
+fn build_time() -> (String, String) {
+    const MONTHS: [&str; 12] = [
+        "Jan", "Feb", "Mar", "Apr", "May", "Jun", "Jul", "Aug", "Sep", "Oct", "Nov", "Dec",
+    ];
+
+    let secs = std::env::var("SOURCE_DATE_EPOCH")
+        .ok()
+        .and_then(|s| s.parse::<i64>().ok())
+        .unwrap_or_else(|| {
+            SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .map_or(0, |d| d.as_secs() as i64)
+        });
+
+    let (days, rem) = (secs.div_euclid(86400), secs.rem_euclid(86400));
+
+    // days since 1970-01-01 to a civil date
+    let z = days + 719468;
+    let era = z.div_euclid(146097);
+    let doe = z.rem_euclid(146097);
+    let yoe = (doe - doe / 1460 + doe / 36524 - doe / 146096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = doy - (153 * mp + 2) / 5 + 1;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 };
+    let year = yoe + era * 400 + (month <= 2) as i64;
+
+    (
+        format!("{} {:2} {}", MONTHS[month as usize - 1], day, year),
+        format!("{:02}:{:02}:{:02}", rem / 3600, rem % 3600 / 60, rem % 60),
+    )
+}
+
+// length of the identifier or number at the start of `s`
+fn ident_end(s: &str) -> usize {
+    s.find(|c: char| !c.is_ascii_alphanumeric() && c != '_')
+        .unwrap_or(s.len())
+}
+
+// splits macro arguments at top level commas, `s` starts after the opening parenthesis.
+// returns the arguments and the text after the closing parenthesis
+fn split_args(s: &str, line: u32) -> Result<(Vec<&str>, &str), Error> {
+    let mut args = Vec::new();
+    let mut depth = 0;
+    let mut start = 0;
+
+    for (i, c) in s.char_indices() {
+        match c {
+            '(' => depth += 1,
+            ')' if depth == 0 => {
+                args.push(s[start..i].trim());
+                return Ok((args, &s[i + 1..]));
+            }
+            ')' => depth -= 1,
+            ',' if depth == 0 => {
+                args.push(s[start..i].trim());
+                start = i + 1;
+            }
+            _ => {}
+        }
+    }
+
+    Err(Error::Syntax {
+        line,
+        msg: "Unterminated macro argument list",
+    })
+}
+
 #[derive(Eq, PartialEq, Debug, Copy, Clone)]
 enum State {
     // A condition already matched, skip remaining clauses
@@ -229,23 +798,23 @@ enum State {
 ///
 /// # Errors
 ///
-/// This function returns a result and can fail with Err(minipre::Error).
+/// This function returns a result and can fail with Err(rust_as::minipre::Error).
 ///
 /// # Examples
 ///
 /// ```
-/// assert_eq!(minipre::process_str("
+/// assert_eq!(rust_as::minipre::process_str("
 ///     #if FOO
 ///     foo text
 ///     #endif
-///     bar text", minipre::Context::new().define("FOO", "1")).unwrap(), "
+///     bar text", rust_as::minipre::Context::new().define("FOO", "1")).unwrap(), "
 ///     foo text
 ///     bar text");
-/// assert_eq!(minipre::process_str("
+/// assert_eq!(rust_as::minipre::process_str("
 ///     #if FOO
 ///     foo text
 ///     #endif
-///     bar text", minipre::Context::new().define("FOO", "0")).unwrap(), "
+///     bar text", rust_as::minipre::Context::new().define("FOO", "0")).unwrap(), "
 ///     bar text");
 /// ```
 pub fn process_str(input: &str, context: &mut Context) -> Result<String, Error> {
@@ -254,6 +823,26 @@ pub fn process_str(input: &str, context: &mut Context) -> Result<String, Error>
     Ok(String::from_utf8(output).expect("Input was utf8, so output should be too..."))
 }
 
+/// Preprocesses a file, resolving `#include "file"` relative to its directory.
+///
+/// # Errors
+///
+/// Errors inside included files are wrapped in Err(rust_as::minipre::Error::Include) with the
+/// path of the file where they occurred.
+pub fn process_file<P: AsRef<Path>>(path: P, context: &mut Context) -> Result<String, Error> {
+    let path = path.as_ref();
+    let mut output = Vec::new();
+    let input = BufReader::new(File::open(path)?);
+
+    let id = context.map.add_file(path.display().to_string());
+    context.files.push((path.to_path_buf(), id));
+    let result = process_lines(&mut { input }, &mut output, context);
+    context.files.pop();
+
+    result?;
+    Ok(String::from_utf8(output).expect("Input was utf8, so output should be too..."))
+}
+
 /// Preprocesses a generic buffer.
 ///
 /// This function takes any generic BufRead input and Write output and preprocesses it.
@@ -262,12 +851,12 @@ pub fn process_str(input: &str, context: &mut Context) -> Result<String, Error>
 ///
 /// ```
 /// let mut output = Vec::new();
-/// minipre::process("
+/// rust_as::minipre::process("
 ///     foo text
 ///     #if !FOO
 ///     more text
 ///     #endif
-///     bar text".as_bytes(), &mut output, minipre::Context::new().define("FOO", "0"));
+///     bar text".as_bytes(), &mut output, rust_as::minipre::Context::new().define("FOO", "0"));
 ///
 /// assert_eq!(String::from_utf8(output).unwrap(), "
 ///     foo text
@@ -277,6 +866,42 @@ pub fn process_str(input: &str, context: &mut Context) -> Result<String, Error>
 pub fn process<I: BufRead, O: Write>(
     mut input: I,
     mut output: O,
+    context: &mut Context,
+) -> Result<(), Error> {
+    process_lines(&mut input, &mut output, context)
+}
+
+fn include(path: PathBuf, output: &mut dyn Write, context: &mut Context) -> Result<(), Error> {
+    let wrap = |error| Error::Include {
+        file: path.display().to_string(),
+        error: Box::new(error),
+    };
+
+    let mut input = BufReader::new(File::open(&path).map_err(|e| wrap(e.into()))?);
+    let mut included = Vec::new();
+
+    let id = context.map.add_file(path.display().to_string());
+    context.files.push((path.clone(), id));
+    let result = process_lines(&mut input, &mut included, context);
+    context.files.pop();
+
+    match result {
+        Err(e @ Error::Include { .. }) => return Err(e),
+        Err(e) => return Err(wrap(e)),
+        Ok(()) => {}
+    }
+
+    // the line after the include must not run into the last line of the file
+    if !included.is_empty() && !included.ends_with(b"\n") {
+        included.push(b'\n');
+    }
+    output.write_all(&included)?;
+    Ok(())
+}
+
+fn process_lines(
+    input: &mut dyn BufRead,
+    output: &mut dyn Write,
     mut context: &mut Context,
 ) -> Result<(), Error> {
     let mut buf = String::new();
@@ -288,11 +913,20 @@ pub fn process<I: BufRead, O: Write>(
     while input.read_line(&mut buf)? > 0 {
         line += 1;
         {
-            let regex = context.build_regex();
-            let new_line = regex.replace_all(&buf, context.replacer().by_ref());
+            // the names in #define, #undef, #ifdef and #ifndef and the messages of #error and
+            // #warning are not expanded, skipped lines are not either
+            let raw = buf.trim_start();
+            let verbatim = ["#define", "#undef", "#ifdef", "#ifndef", "#error", "#warning"];
+            let new_line = if verbatim.iter().any(|d| raw.split_whitespace().next() == Some(d)) {
+                Cow::Borrowed(buf.as_str())
+            } else if state == State::Active || raw.starts_with('#') {
+                Cow::Owned(context.expand(&buf, line)?)
+            } else {
+                Cow::Borrowed(buf.as_str())
+            };
             let substr = new_line.trim();
             if substr.starts_with("#") {
-                let mut parts = substr.splitn(2, "//").next().unwrap().splitn(2, " ");
+                let mut parts = substr.split("//").next().unwrap().splitn(2, " ");
                 let name = parts.next().unwrap();
                 let maybe_expr = parts.next().map(|s| s.trim()).and_then(|s| {
                     if s.is_empty() {
@@ -304,11 +938,11 @@ pub fn process<I: BufRead, O: Write>(
 
                 match name {
                     "#if" => {
-                        let expr = maybe_expr.ok_or_else(|| Error::Syntax {
+                        let expr = maybe_expr.ok_or(Error::Syntax {
                             line,
                             msg: "Expected expression after `#if`",
                         })?;
-                        stack.push(state);
+                        stack.push((state, line));
                         if state == State::Active {
                             if !context.evaluate(expr, line)? {
                                 state = State::Inactive;
@@ -317,8 +951,38 @@ pub fn process<I: BufRead, O: Write>(
                             state = State::Skip;
                         }
                     }
+                    "#ifdef" | "#ifndef" => {
+                        let macro_name = maybe_expr.ok_or(Error::Syntax {
+                            line,
+                            msg: "Expected macro name",
+                        })?;
+                        stack.push((state, line));
+                        if state == State::Active {
+                            let defined = context.defs.contains_key(macro_name) || is_builtin(macro_name);
+                            if defined != (name == "#ifdef") {
+                                state = State::Inactive;
+                            }
+                        } else {
+                            state = State::Skip;
+                        }
+                    }
+                    "#error" if state == State::Active => {
+                        return Err(Error::User {
+                            line,
+                            msg: maybe_expr.unwrap_or("#error").to_string(),
+                        });
+                    }
+                    "#warning" if state == State::Active => {
+                        let file = match context.files.last() {
+                            Some((path, _)) => path.display().to_string(),
+                            None => "<input>".to_string(),
+                        };
+                        let msg = maybe_expr.unwrap_or("#warning").to_string();
+                        context.warnings.push((file, line, msg));
+                    }
+                    "#error" | "#warning" => {}
                     "#elif" => {
-                        let expr = maybe_expr.ok_or_else(|| Error::Syntax {
+                        let expr = maybe_expr.ok_or(Error::Syntax {
                             line,
                             msg: "Expected expression after `#elif`",
                         })?;
@@ -350,11 +1014,72 @@ pub fn process<I: BufRead, O: Write>(
                                 msg: "Unexpected expression after `#else`",
                             });
                         }
-                        state = stack.pop().ok_or_else(|| Error::Syntax {
+                        (state, _) = stack.pop().ok_or(Error::Syntax {
                             line,
                             msg: "Unexpected `#endif` with no matching `#if`",
                         })?;
                     }
+                    "#include" if state == State::Active => {
+                        let expr = maybe_expr.ok_or(Error::Syntax {
+                            line,
+                            msg: "Expected file name after `#include`",
+                        })?;
+                        let (name, quoted) = if let Some(name) =
+                            expr.strip_prefix('"').and_then(|e| e.strip_suffix('"'))
+                        {
+                            (name, true)
+                        } else if let Some(name) =
+                            expr.strip_prefix('<').and_then(|e| e.strip_suffix('>'))
+                        {
+                            (name, false)
+                        } else {
+                            return Err(Error::Syntax {
+                                line,
+                                msg: "Expected \"file\" or <file> after `#include`",
+                            });
+                        };
+
+                        let path = context.find_include(name, quoted).ok_or_else(|| {
+                            Error::Included {
+                                line,
+                                msg: format!("cannot find include file `{}`", name),
+                            }
+                        })?;
+                        let canonical = path.canonicalize().unwrap_or(path.clone());
+                        if context.once.contains(&canonical) {
+                            buf.clear();
+                            continue;
+                        }
+                        if context
+                            .files
+                            .iter()
+                            .any(|(p, _)| p.canonicalize().unwrap_or(p.clone()) == canonical)
+                        {
+                            let chain: Vec<String> = context
+                                .files
+                                .iter()
+                                .map(|(p, _)| p.display().to_string())
+                                .chain(std::iter::once(path.display().to_string()))
+                                .collect();
+                            return Err(Error::Included {
+                                line,
+                                msg: format!("include cycle: {}", chain.join(" -> ")),
+                            });
+                        }
+
+                        include(path, output, context)?;
+                        buf.clear();
+                        continue;
+                    }
+                    "#include" => {}
+                    "#pragma" => {
+                        if let (Some("once"), State::Active, Some((path, _))) =
+                            (maybe_expr, state, context.files.last())
+                        {
+                            let canonical = path.canonicalize().unwrap_or(path.clone());
+                            context.once.push(canonical);
+                        }
+                    }
                     "#define" => {
                         let Some(e) = maybe_expr else {
                             return Err(Error::Syntax {
@@ -363,11 +1088,21 @@ pub fn process<I: BufRead, O: Write>(
                             });
                         };
 
-                        let mut e = e.split_whitespace();
-                        let name = e.next().unwrap();
-                        let e = e.collect::<Vec<_>>().join(" ");
+                        if state == State::Active {
+                            context.parse_define(e, line)?;
+                        }
+                    }
+                    "#undef" => {
+                        let Some(name) = maybe_expr else {
+                            return Err(Error::Syntax {
+                                line,
+                                msg: "Expected macro name after `#undef`",
+                            });
+                        };
 
-                        context = context.define(name, e);
+                        if state == State::Active {
+                            context = context.undefine(name);
+                        }
                     }
                     _ => {
                         return Err(Error::Syntax {
@@ -376,11 +1111,26 @@ pub fn process<I: BufRead, O: Write>(
                         });
                     }
                 }
+                if context.keep_lines && buf.ends_with('\n') {
+                    output.write_all(b"\n")?;
+                    context.record_line(line);
+                }
             } else if state == State::Active {
                 output.write_all(new_line.as_bytes())?;
+                context.record_line(line);
+            } else if context.keep_lines && buf.ends_with('\n') {
+                output.write_all(b"\n")?;
+                context.record_line(line);
             }
         }
         buf.clear();
     }
+
+    if let Some(&(_, line)) = stack.last() {
+        return Err(Error::Syntax {
+            line,
+            msg: "Unterminated `#if`, expected `#endif`",
+        });
+    }
     Ok(())
 }
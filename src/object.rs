@@ -0,0 +1,452 @@
+// relocatable ELF64 object files with a custom machine number
+
+use crate::layout::MAX_SECTION_SIZE;
+
+
+pub const EM_RUST_AS: u16 = 0x7273;
+
+pub const R_RUST_AS_8: u32 = 1;
+pub const R_RUST_AS_16: u32 = 2;
+pub const R_RUST_AS_32: u32 = 3;
+pub const R_RUST_AS_64: u32 = 4;
+
+const SHT_PROGBITS: u32 = 1;
+const SHT_SYMTAB: u32 = 2;
+const SHT_STRTAB: u32 = 3;
+const SHT_RELA: u32 = 4;
+const SHT_NOBITS: u32 = 8;
+
+const SHF_WRITE: u64 = 1;
+const SHF_ALLOC: u64 = 2;
+const SHF_EXECINSTR: u64 = 4;
+const SHF_INFO_LINK: u64 = 0x40;
+
+const STB_LOCAL: u8 = 0;
+const STB_GLOBAL: u8 = 1;
+const STT_NOTYPE: u8 = 0;
+const STT_SECTION: u8 = 3;
+
+const SHN_ABS: u16 = 0xfff1;
+
+
+#[derive(Debug, Clone)]
+pub struct Section {
+	pub name: String,
+	// empty for .bss sections, which only have a size
+	pub data: Vec<u8>,
+	pub size: u64,
+	pub align: u64,
+	pub bss: bool,
+	pub relocs: Vec<Relocation>,
+}
+
+#[derive(Debug, Clone)]
+pub struct Symbol {
+	pub name: String,
+	// index into Object::sections, None for undefined and absolute symbols
+	pub section: Option<usize>,
+	pub value: u64,
+	pub global: bool,
+	// the value is a plain number rather than an offset into a section
+	pub absolute: bool,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Target {
+	Section(usize),
+	Symbol(usize),
+}
+
+#[derive(Debug, Clone)]
+pub struct Relocation {
+	pub offset: u64,
+	pub size: u8,
+	pub target: Target,
+	pub addend: i64,
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct Object {
+	pub sections: Vec<Section>,
+	pub symbols: Vec<Symbol>,
+}
+
+
+pub fn reloc_type(size: u8) -> u32 {
+	match size {
+		1 => R_RUST_AS_8,
+		2 => R_RUST_AS_16,
+		4 => R_RUST_AS_32,
+		8 => R_RUST_AS_64,
+		_ => unreachable!(),
+	}
+}
+
+
+struct Strtab {
+	data: Vec<u8>,
+}
+
+impl Strtab {
+	fn new() -> Self {
+		Strtab { data: vec![0] }
+	}
+
+	fn add(&mut self, s: &str) -> u32 {
+		if s.is_empty() {
+			return 0;
+		}
+		let index = self.data.len() as u32;
+		self.data.extend(s.as_bytes());
+		self.data.push(0);
+		index
+	}
+}
+
+
+#[allow(clippy::too_many_arguments)]
+fn section_header(
+	out: &mut Vec<u8>,
+	name: u32,
+	kind: u32,
+	flags: u64,
+	offset: u64,
+	size: u64,
+	link: u32,
+	info: u32,
+	align: u64,
+	entsize: u64,
+) {
+	out.extend(name.to_le_bytes());
+	out.extend(kind.to_le_bytes());
+	out.extend(flags.to_le_bytes());
+	out.extend(0u64.to_le_bytes());
+	out.extend(offset.to_le_bytes());
+	out.extend(size.to_le_bytes());
+	out.extend(link.to_le_bytes());
+	out.extend(info.to_le_bytes());
+	out.extend(align.to_le_bytes());
+	out.extend(entsize.to_le_bytes());
+}
+
+
+fn section_flags(name: &str) -> u64 {
+	if name == ".text" || name.starts_with(".text.") {
+		SHF_ALLOC | SHF_EXECINSTR
+	} else {
+		SHF_ALLOC | SHF_WRITE
+	}
+}
+
+
+impl Object {
+	pub fn write(&self) -> Vec<u8> {
+		let mut shstrtab = Strtab::new();
+		let mut strtab = Strtab::new();
+
+		// section headers: null, then every section followed by its .rela section,
+		// then .symtab, .strtab and .shstrtab
+		let mut section_index = vec![];
+		let mut next = 1;
+		for s in &self.sections {
+			section_index.push(next);
+			next += if s.relocs.is_empty() { 1 } else { 2 };
+		}
+		let symtab_index = next;
+		let strtab_index = next + 1;
+		let shstrtab_index = next + 2;
+		let shnum = next + 3;
+
+		// symbol table: null, section symbols, local symbols, global symbols
+		let mut symtab: Vec<u8> = vec![0; 24];
+		let mut symbol_index = vec![0; self.symbols.len()];
+		let mut count = 1;
+
+		let mut put_symbol = |symtab: &mut Vec<u8>, name: u32, info: u8, shndx: u16, value: u64| {
+			symtab.extend(name.to_le_bytes());
+			symtab.push(info);
+			symtab.push(0);
+			symtab.extend(shndx.to_le_bytes());
+			symtab.extend(value.to_le_bytes());
+			symtab.extend(0u64.to_le_bytes());
+			count += 1;
+			count - 1
+		};
+
+		for i in &section_index {
+			put_symbol(&mut symtab, 0, (STB_LOCAL << 4) | STT_SECTION, *i as u16, 0);
+		}
+
+		for global in [false, true] {
+			for (n, s) in self.symbols.iter().enumerate() {
+				if s.global != global {
+					continue;
+				}
+				let bind = if global { STB_GLOBAL } else { STB_LOCAL };
+				let shndx = if s.absolute { SHN_ABS } else { s.section.map_or(0, |i| section_index[i] as u16) };
+				symbol_index[n] = put_symbol(
+					&mut symtab,
+					strtab.add(&s.name),
+					(bind << 4) | STT_NOTYPE,
+					shndx,
+					s.value,
+				);
+			}
+		}
+
+		let first_global = 1 + self.sections.len() as u32 +
+			self.symbols.iter().filter(|s| !s.global).count() as u32;
+
+		// section contents follow the ELF header, section headers go last
+		let mut body: Vec<u8> = vec![];
+		let mut headers: Vec<u8> = vec![0; 64];
+
+		let place = |body: &mut Vec<u8>, data: &[u8]| {
+			body.resize(body.len().div_ceil(8) * 8, 0);
+			let offset = 64 + body.len() as u64;
+			body.extend(data);
+			offset
+		};
+
+		for (n, s) in self.sections.iter().enumerate() {
+			let offset = place(&mut body, &s.data);
+			let kind = if s.bss { SHT_NOBITS } else { SHT_PROGBITS };
+			section_header(
+				&mut headers, shstrtab.add(&s.name), kind, section_flags(&s.name),
+				offset, s.size, 0, 0, s.align, 0,
+			);
+
+			if s.relocs.is_empty() {
+				continue;
+			}
+
+			let mut rela: Vec<u8> = vec![];
+			for r in &s.relocs {
+				let sym = match r.target {
+					Target::Section(i) => 1 + i as u64,
+					Target::Symbol(i) => symbol_index[i] as u64,
+				};
+				rela.extend(r.offset.to_le_bytes());
+				rela.extend(((sym << 32) | reloc_type(r.size) as u64).to_le_bytes());
+				rela.extend(r.addend.to_le_bytes());
+			}
+
+			let offset = place(&mut body, &rela);
+			section_header(
+				&mut headers, shstrtab.add(&format!(".rela{}", s.name)), SHT_RELA, SHF_INFO_LINK,
+				offset, rela.len() as u64, symtab_index, section_index[n], 8, 24,
+			);
+		}
+
+		let offset = place(&mut body, &symtab);
+		section_header(
+			&mut headers, shstrtab.add(".symtab"), SHT_SYMTAB, 0,
+			offset, symtab.len() as u64, strtab_index, first_global, 8, 24,
+		);
+
+		let offset = place(&mut body, &strtab.data);
+		section_header(
+			&mut headers, shstrtab.add(".strtab"), SHT_STRTAB, 0,
+			offset, strtab.data.len() as u64, 0, 0, 1, 0,
+		);
+
+		let name = shstrtab.add(".shstrtab");
+		let offset = place(&mut body, &shstrtab.data);
+		section_header(
+			&mut headers, name, SHT_STRTAB, 0,
+			offset, shstrtab.data.len() as u64, 0, 0, 1, 0,
+		);
+
+		body.resize(body.len().div_ceil(8) * 8, 0);
+		let shoff = 64 + body.len() as u64;
+
+		let mut out: Vec<u8> = vec![0x7f, b'E', b'L', b'F', 2, 1, 1, 0, 0, 0, 0, 0, 0, 0, 0, 0];
+		out.extend(1u16.to_le_bytes());          // e_type = ET_REL
+		out.extend(EM_RUST_AS.to_le_bytes());    // e_machine
+		out.extend(1u32.to_le_bytes());          // e_version
+		out.extend(0u64.to_le_bytes());          // e_entry
+		out.extend(0u64.to_le_bytes());          // e_phoff
+		out.extend(shoff.to_le_bytes());         // e_shoff
+		out.extend(0u32.to_le_bytes());          // e_flags
+		out.extend(64u16.to_le_bytes());         // e_ehsize
+		out.extend(0u16.to_le_bytes());          // e_phentsize
+		out.extend(0u16.to_le_bytes());          // e_phnum
+		out.extend(64u16.to_le_bytes());         // e_shentsize
+		out.extend((shnum as u16).to_le_bytes());
+		out.extend((shstrtab_index as u16).to_le_bytes());
+
+		out.extend(body);
+		out.extend(headers);
+		out
+	}
+}
+
+
+fn read_u16(bytes: &[u8], at: usize) -> Result<u16, String> {
+	bytes.get(at..).and_then(|b| b.get(..2))
+		.map(|b| u16::from_le_bytes(b.try_into().unwrap()))
+		.ok_or("unexpected end of file".to_string())
+}
+
+fn read_u32(bytes: &[u8], at: usize) -> Result<u32, String> {
+	bytes.get(at..).and_then(|b| b.get(..4))
+		.map(|b| u32::from_le_bytes(b.try_into().unwrap()))
+		.ok_or("unexpected end of file".to_string())
+}
+
+fn read_u64(bytes: &[u8], at: usize) -> Result<u64, String> {
+	bytes.get(at..).and_then(|b| b.get(..8))
+		.map(|b| u64::from_le_bytes(b.try_into().unwrap()))
+		.ok_or("unexpected end of file".to_string())
+}
+
+fn read_str(bytes: &[u8], at: usize) -> Result<String, String> {
+	let tail = bytes.get(at..).ok_or("string out of bounds")?;
+	let len = tail.iter().position(|b| *b == 0).ok_or("unterminated string")?;
+	String::from_utf8(tail[..len].to_vec()).map_err(|_| "invalid string".to_string())
+}
+
+
+struct SectionHeader {
+	name: u32,
+	kind: u32,
+	offset: usize,
+	size: usize,
+	link: u32,
+	info: u32,
+	align: u64,
+}
+
+
+impl Object {
+	pub fn read(bytes: &[u8]) -> Result<Object, String> {
+		if bytes.get(0..6) != Some(&[0x7f, b'E', b'L', b'F', 2, 1]) {
+			return Err("not a little-endian ELF64 file".to_string());
+		}
+		if read_u16(bytes, 16)? != 1 {
+			return Err("not a relocatable object".to_string());
+		}
+		if read_u16(bytes, 18)? != EM_RUST_AS {
+			return Err("object was not produced for this architecture".to_string());
+		}
+
+		let shoff = read_u64(bytes, 40)? as usize;
+		let shnum = read_u16(bytes, 60)? as usize;
+		let shstrndx = read_u16(bytes, 62)? as usize;
+
+		let mut headers = vec![];
+		for i in 0..shnum {
+			let at = shoff.checked_add(i * 64).ok_or("section headers out of bounds")?;
+			headers.push(SectionHeader {
+				name: read_u32(bytes, at)?,
+				kind: read_u32(bytes, at + 4)?,
+				offset: read_u64(bytes, at + 24)? as usize,
+				size: read_u64(bytes, at + 32)? as usize,
+				link: read_u32(bytes, at + 40)?,
+				info: read_u32(bytes, at + 44)?,
+				align: read_u64(bytes, at + 48)?,
+			});
+		}
+
+		let contents = |h: &SectionHeader| {
+			if h.kind == SHT_NOBITS {
+				return Ok(&[][..]);
+			}
+			h.offset.checked_add(h.size)
+				.and_then(|end| bytes.get(h.offset..end))
+				.ok_or("section out of bounds".to_string())
+		};
+
+		let shstrtab = contents(headers.get(shstrndx).ok_or("missing section name table")?)?;
+
+		let mut object = Object::default();
+		// elf section index -> index into object.sections
+		let mut section_map = vec![None; shnum];
+
+		for (i, h) in headers.iter().enumerate() {
+			if h.kind == SHT_PROGBITS || h.kind == SHT_NOBITS {
+				if h.size as u64 > MAX_SECTION_SIZE {
+					return Err(format!("section larger than {MAX_SECTION_SIZE:#x} bytes"));
+				}
+				section_map[i] = Some(object.sections.len());
+				object.sections.push(Section {
+					name: read_str(shstrtab, h.name as usize)?,
+					data: contents(h)?.to_vec(),
+					size: h.size as u64,
+					align: h.align.max(1),
+					bss: h.kind == SHT_NOBITS,
+					relocs: vec![],
+				});
+			}
+		}
+
+		// elf symbol index -> relocation target
+		let mut targets = vec![];
+
+		if let Some(symtab) = headers.iter().find(|h| h.kind == SHT_SYMTAB) {
+			let strtab = contents(headers.get(symtab.link as usize).ok_or("missing string table")?)?;
+			let data = contents(symtab)?;
+
+			for i in 0..data.len() / 24 {
+				let at = i * 24;
+				let info = data[at + 4];
+				let shndx = read_u16(data, at + 6)?;
+				let absolute = shndx == SHN_ABS;
+				let shndx = shndx as usize;
+				let section = if shndx == 0 || absolute { None } else {
+					Some(section_map.get(shndx).copied().flatten().ok_or("symbol in unknown section")?)
+				};
+
+				if i == 0 {
+					targets.push(None);
+				} else if info & 0xf == STT_SECTION {
+					targets.push(section.map(Target::Section));
+				} else {
+					targets.push(Some(Target::Symbol(object.symbols.len())));
+					object.symbols.push(Symbol {
+						name: read_str(strtab, read_u32(data, at)? as usize)?,
+						section,
+						value: read_u64(data, at + 8)?,
+						global: info >> 4 != STB_LOCAL,
+						absolute,
+					});
+				}
+			}
+		}
+
+		for h in headers.iter().filter(|h| h.kind == SHT_RELA) {
+			let section = section_map.get(h.info as usize).copied().flatten()
+				.ok_or("relocations for unknown section")?;
+			let data = contents(h)?;
+
+			for i in 0..data.len() / 24 {
+				let at = i * 24;
+				let info = read_u64(data, at + 8)?;
+				let size = match info as u32 {
+					R_RUST_AS_8 => 1,
+					R_RUST_AS_16 => 2,
+					R_RUST_AS_32 => 4,
+					R_RUST_AS_64 => 8,
+					t => return Err(format!("unknown relocation type {t}")),
+				};
+				let target = targets.get((info >> 32) as usize).copied().flatten()
+					.ok_or("relocation against unknown symbol")?;
+
+				let offset = read_u64(data, at)?;
+				let s = &mut object.sections[section];
+				if s.bss || offset.checked_add(size as u64).is_none_or(|end| end > s.size) {
+					return Err(format!("relocation at {}+{offset:#x} out of bounds", s.name));
+				}
+
+				s.relocs.push(Relocation {
+					offset,
+					size,
+					target,
+					addend: read_u64(data, at + 16)? as i64,
+				});
+			}
+		}
+
+		Ok(object)
+	}
+}
@@ -1,16 +1,24 @@
+use std::collections::VecDeque;
+
+use logos::Span;
+
 use super::token::Token;
-use super::expr::{Expr, ExprKind};
+use super::expr::{Expr, ExprKind, InstrArgs, INSTRS};
+use super::diag::Diagnostic;
+use super::asm::{datatype, directive, directive_args, instruction, register};
 
 use Token::*;
 
 
+#[allow(clippy::upper_case_acronyms)]
 pub enum Operation<'a> {
 	NOMATCH,
 	SHIFT(Vec<Token<'a>>),
 	REDUCE(usize, &'a dyn Fn(Vec<Expr<'a>>) -> (Token<'a>, Expr<'a>)),
 }
 
-pub fn reduce<'a>(stack: &Vec<Token<'a>>, lookahead: Token<'a>) -> Operation<'a> {
+#[allow(clippy::single_match, clippy::len_zero)]
+pub fn reduce<'a>(stack: &[Token<'a>], lookahead: Token<'a>) -> Operation<'a> {
 	if stack.len() >= 6 { match (
 		&stack[stack.len() - 6],
 		&stack[stack.len() - 5],
@@ -43,7 +51,19 @@ pub fn reduce<'a>(stack: &Vec<Token<'a>>, lookahead: Token<'a>) -> Operation<'a>
 		&lookahead
 	) {
 		(IName(_), E, COMMA, E, COMMA, _) => return
-			Operation::SHIFT(vec![Reg(""), LBR, Number(0), TILDA, Id("")]),
+			Operation::SHIFT(vec![Reg(""), LBR, Number(0), TILDA, MINUS, PLUS, BANG, Id("")]),
+
+		(E2, QUESTION, E1, COLON, E1, _) => return
+			Operation::REDUCE(5, &|vals| {
+				(E1, Expr{
+					kind: ExprKind::Cond(
+						Box::new(vals[0].clone()), Box::new(vals[2].clone()), Box::new(vals[4].clone())
+					),
+					span: vals[0].span.start..vals[4].span.end,
+					..Default::default()
+				})
+			}),
+
 		_ => {}
 	}}
 
@@ -78,7 +98,16 @@ pub fn reduce<'a>(stack: &Vec<Token<'a>>, lookahead: Token<'a>) -> Operation<'a>
 		&lookahead
 	) {
 		(IName(_), E, COMMA, _) => return
-			Operation::SHIFT(vec![Reg(""), LBR, Number(0), TILDA, Id("")]),
+			Operation::SHIFT(vec![Reg(""), LBR, Number(0), TILDA, MINUS, PLUS, BANG, Id("")]),
+
+		(Id(_), ASSIGN, E, _) => return
+			Operation::REDUCE(3, &|vals| {
+				(Dir, Expr{
+					kind: ExprKind::Directive(".set", vec![vals[0].clone(), vals[2].clone()]),
+					span: vals[0].span.start..vals[2].span.end,
+					..Default::default()
+				})
+			}),
 
 		(Vals, COMMA, E, _) => return
 			Operation::REDUCE(3, &|vals| {
@@ -93,104 +122,180 @@ pub fn reduce<'a>(stack: &Vec<Token<'a>>, lookahead: Token<'a>) -> Operation<'a>
 				} else {unreachable!()}
 			}),
 
-		(DataType(_), Vals, COMMA, _) => return
-			Operation::SHIFT(vec![LBR, Number(0), TILDA, Id("")]),
+		(DataType(_), Vals, COMMA, _) |
+		(Directive(_), Vals, COMMA, _) => return
+			Operation::SHIFT(vec![LBR, Number(0), TILDA, MINUS, PLUS, BANG, Id("")]),
+
+		(E2, QUESTION, E1, _) => return
+			Operation::SHIFT(vec![COLON]),
 
-		(E1, PIPE, E1, _) => return
+		(E2, OROR, E2, _) => return
 			Operation::REDUCE(3, &|vals| {
-				(E1, Expr{
+				(E2, Expr{
+					kind: ExprKind::LOr(Box::new(vals[0].clone()), Box::new(vals[2].clone())),
+					span: vals[0].span.start..vals[2].span.end,
+					..Default::default()
+				})
+			}),
+
+		(E3, ANDAND, E3, _) => return
+			Operation::REDUCE(3, &|vals| {
+				(E3, Expr{
+					kind: ExprKind::LAnd(Box::new(vals[0].clone()), Box::new(vals[2].clone())),
+					span: vals[0].span.start..vals[2].span.end,
+					..Default::default()
+				})
+			}),
+
+		(E4, EQ, E4, _) => return
+			Operation::REDUCE(3, &|vals| {
+				(E4, Expr{
+					kind: ExprKind::Eq(Box::new(vals[0].clone()), Box::new(vals[2].clone())),
+					span: vals[0].span.start..vals[2].span.end,
+					..Default::default()
+				})
+			}),
+
+		(E4, NE, E4, _) => return
+			Operation::REDUCE(3, &|vals| {
+				(E4, Expr{
+					kind: ExprKind::Ne(Box::new(vals[0].clone()), Box::new(vals[2].clone())),
+					span: vals[0].span.start..vals[2].span.end,
+					..Default::default()
+				})
+			}),
+
+		(E5, LT, E5, _) => return
+			Operation::REDUCE(3, &|vals| {
+				(E5, Expr{
+					kind: ExprKind::Lt(Box::new(vals[0].clone()), Box::new(vals[2].clone())),
+					span: vals[0].span.start..vals[2].span.end,
+					..Default::default()
+				})
+			}),
+
+		(E5, LE, E5, _) => return
+			Operation::REDUCE(3, &|vals| {
+				(E5, Expr{
+					kind: ExprKind::Le(Box::new(vals[0].clone()), Box::new(vals[2].clone())),
+					span: vals[0].span.start..vals[2].span.end,
+					..Default::default()
+				})
+			}),
+
+		(E5, GT, E5, _) => return
+			Operation::REDUCE(3, &|vals| {
+				(E5, Expr{
+					kind: ExprKind::Gt(Box::new(vals[0].clone()), Box::new(vals[2].clone())),
+					span: vals[0].span.start..vals[2].span.end,
+					..Default::default()
+				})
+			}),
+
+		(E5, GE, E5, _) => return
+			Operation::REDUCE(3, &|vals| {
+				(E5, Expr{
+					kind: ExprKind::Ge(Box::new(vals[0].clone()), Box::new(vals[2].clone())),
+					span: vals[0].span.start..vals[2].span.end,
+					..Default::default()
+				})
+			}),
+
+		(E6, PIPE, E6, _) => return
+			Operation::REDUCE(3, &|vals| {
+				(E6, Expr{
 					kind: ExprKind::Or(Box::new(vals[0].clone()), Box::new(vals[2].clone())),
-					span: vals[0].span.start..vals[2].span.start,
+					span: vals[0].span.start..vals[2].span.end,
 					..Default::default()
 				})
 			}),
 
-		(E2, CARET, E2, _) => return
+		(E7, CARET, E7, _) => return
 			Operation::REDUCE(3, &|vals| {
-				(E2, Expr{
+				(E7, Expr{
 					kind: ExprKind::Xor(Box::new(vals[0].clone()), Box::new(vals[2].clone())),
-					span: vals[0].span.start..vals[2].span.start,
+					span: vals[0].span.start..vals[2].span.end,
 					..Default::default()
 				})
 			}),
 
-		(E3, AMPERSAND, E3, _) => return
+		(E8, AMPERSAND, E8, _) => return
 			Operation::REDUCE(3, &|vals| {
-				(E3, Expr{
+				(E8, Expr{
 					kind: ExprKind::And(Box::new(vals[0].clone()), Box::new(vals[2].clone())),
-					span: vals[0].span.start..vals[2].span.start,
+					span: vals[0].span.start..vals[2].span.end,
 					..Default::default()
 				})
 			}),
 
-		(E4, LSHIFT, E4, _) => return
+		(E9, LSHIFT, E9, _) => return
 			Operation::REDUCE(3, &|vals| {
-				(E4, Expr{
+				(E9, Expr{
 					kind: ExprKind::Lsh(Box::new(vals[0].clone()), Box::new(vals[2].clone())),
-					span: vals[0].span.start..vals[2].span.start,
+					span: vals[0].span.start..vals[2].span.end,
 					..Default::default()
 				})
 			}),
 
-		(E4, RSHIFT, E4, _) => return
+		(E9, RSHIFT, E9, _) => return
 			Operation::REDUCE(3, &|vals| {
-				(E4, Expr{
+				(E9, Expr{
 					kind: ExprKind::Rsh(Box::new(vals[0].clone()), Box::new(vals[2].clone())),
-					span: vals[0].span.start..vals[2].span.start,
+					span: vals[0].span.start..vals[2].span.end,
 					..Default::default()
 				})
 			}),
 
-		(E5, PLUS, E5, _) => return
+		(E10, PLUS, E10, _) => return
 			Operation::REDUCE(3, &|vals| {
-				(E5, Expr{
+				(E10, Expr{
 					kind: ExprKind::Sum(Box::new(vals[0].clone()), Box::new(vals[2].clone())),
-					span: vals[0].span.start..vals[2].span.start,
+					span: vals[0].span.start..vals[2].span.end,
 					..Default::default()
 				})
 			}),
 
-		(E5, MINUS, E5, _) => return
+		(E10, MINUS, E10, _) => return
 			Operation::REDUCE(3, &|vals| {
-				(E5, Expr{
+				(E10, Expr{
 					kind: ExprKind::Sub(Box::new(vals[0].clone()), Box::new(vals[2].clone())),
-					span: vals[0].span.start..vals[2].span.start,
+					span: vals[0].span.start..vals[2].span.end,
 					..Default::default()
 				})
 			}),
 
-		(E6, PERCENT, E6, _) => return
+		(E11, PERCENT, E11, _) => return
 			Operation::REDUCE(3, &|vals| {
-				(E6, Expr{
+				(E11, Expr{
 					kind: ExprKind::Mod(Box::new(vals[0].clone()), Box::new(vals[2].clone())),
-					span: vals[0].span.start..vals[2].span.start,
+					span: vals[0].span.start..vals[2].span.end,
 					..Default::default()
 				})
 			}),
 
-		(E6, STAR, E6, _) => return
+		(E11, STAR, E11, _) => return
 			Operation::REDUCE(3, &|vals| {
-				(E6, Expr{
+				(E11, Expr{
 					kind: ExprKind::Mul(Box::new(vals[0].clone()), Box::new(vals[2].clone())),
-					span: vals[0].span.start..vals[2].span.start,
+					span: vals[0].span.start..vals[2].span.end,
 					..Default::default()
 				})
 			}),
 
-		(E6, SLASH, E6, _) => return
+		(E11, SLASH, E11, _) => return
 			Operation::REDUCE(3, &|vals| {
-				(E6, Expr{
+				(E11, Expr{
 					kind: ExprKind::Div(Box::new(vals[0].clone()), Box::new(vals[2].clone())),
-					span: vals[0].span.start..vals[2].span.start,
+					span: vals[0].span.start..vals[2].span.end,
 					..Default::default()
 				})
 			}),
 
 		(LBR, E1, RBR, _) => return
 			Operation::REDUCE(3, &|vals| {
-				(E8, Expr{
+				(E13, Expr{
 					kind: vals[1].kind.clone(),
-					span: vals[0].span.start..vals[2].span.start,
+					span: vals[0].span.start..vals[2].span.end,
 					..Default::default()
 				})
 			}),
@@ -206,22 +311,34 @@ pub fn reduce<'a>(stack: &Vec<Token<'a>>, lookahead: Token<'a>) -> Operation<'a>
 		(IName(_), E, COMMA) => return
 			Operation::SHIFT(vec![COMMA]),
 
-		(E1, PIPE, _) |
-		(E2, CARET, _) |
-		(E3, AMPERSAND, _) |
-		(E4, LSHIFT, _) |
-		(E4, RSHIFT, _) |
-		(E5, PLUS, _) |
-		(E5, MINUS, _) |
-		(E6, PERCENT, _) |
-		(E6, STAR, _) |
-		(E6, SLASH, _) => return
-			Operation::SHIFT(vec![LBR, Number(0), TILDA, Id("")]),
+		(Id(_), ASSIGN, _) |
+		(E2, QUESTION, _) |
+		(E1, COLON, _) |
+		(E2, OROR, _) |
+		(E3, ANDAND, _) |
+		(E4, EQ, _) |
+		(E4, NE, _) |
+		(E5, LT, _) |
+		(E5, LE, _) |
+		(E5, GT, _) |
+		(E5, GE, _) |
+		(E6, PIPE, _) |
+		(E7, CARET, _) |
+		(E8, AMPERSAND, _) |
+		(E9, LSHIFT, _) |
+		(E9, RSHIFT, _) |
+		(E10, PLUS, _) |
+		(E10, MINUS, _) |
+		(E11, PERCENT, _) |
+		(E11, STAR, _) |
+		(E11, SLASH, _) => return
+			Operation::SHIFT(vec![LBR, Number(0), TILDA, MINUS, PLUS, BANG, Id("")]),
 
 		(LBR, E1, _) => return
-			Operation::SHIFT(vec![RBR, PIPE, CARET, AMPERSAND, LSHIFT, RSHIFT, PLUS, MINUS, PERCENT, STAR, SLASH]),
+			Operation::SHIFT(vec![RBR, QUESTION, OROR, ANDAND, EQ, NE, LT, LE, GT, GE, PIPE, CARET, AMPERSAND, LSHIFT, RSHIFT, PLUS, MINUS, PERCENT, STAR, SLASH]),
 
-		(DataType(_), Vals, COMMA) => return
+		(DataType(_), Vals, COMMA) |
+		(Directive(_), Vals, COMMA) => return
 			Operation::SHIFT(vec![COMMA]),
 
 		(DataType(_), Vals, _) => return
@@ -236,9 +353,49 @@ pub fn reduce<'a>(stack: &Vec<Token<'a>>, lookahead: Token<'a>) -> Operation<'a>
 				} else {unreachable!()}
 			}),
 
-		(TILDA, E7, _) => return
+		(Directive(_), Vals, _) => return
 			Operation::REDUCE(2, &|vals| {
-				(E7, Expr{
+				if let (ExprKind::DName(name), ExprKind::Vals(arr)) =
+					(vals[0].kind.clone(), vals[1].kind.clone()) {
+					(Dir, Expr{
+						kind: ExprKind::Directive(name, arr),
+						span: vals[0].span.start..vals[1].span.end,
+						..Default::default()
+					})
+				} else {unreachable!()}
+			}),
+
+		// a minus or plus after an operand is a binary operator
+		(MINUS, E12, _) if stack.len() < 3 || stack[stack.len() - 3] != E10 => return
+			Operation::REDUCE(2, &|vals| {
+				(E12, Expr{
+					kind: ExprKind::Neg(Box::new(vals[1].clone())),
+					span: vals[0].span.start..vals[1].span.end,
+					..Default::default()
+				})
+			}),
+
+		(PLUS, E12, _) if stack.len() < 3 || stack[stack.len() - 3] != E10 => return
+			Operation::REDUCE(2, &|vals| {
+				(E12, Expr{
+					kind: vals[1].kind.clone(),
+					span: vals[0].span.start..vals[1].span.end,
+					..Default::default()
+				})
+			}),
+
+		(BANG, E12, _) => return
+			Operation::REDUCE(2, &|vals| {
+				(E12, Expr{
+					kind: ExprKind::LNot(Box::new(vals[1].clone())),
+					span: vals[0].span.start..vals[1].span.end,
+					..Default::default()
+				})
+			}),
+
+		(TILDA, E12, _) => return
+			Operation::REDUCE(2, &|vals| {
+				(E12, Expr{
 					kind: ExprKind::Not(Box::new(vals[1].clone())),
 					span: vals[0].span.start..vals[1].span.end,
 					..Default::default()
@@ -264,51 +421,108 @@ pub fn reduce<'a>(stack: &Vec<Token<'a>>, lookahead: Token<'a>) -> Operation<'a>
 		&stack[stack.len() - 1],
 		&lookahead
 	) {
+		(IName(n), _) if matches!(INSTRS[instruction(n).0 as usize][0], InstrArgs::None) => return
+			Operation::REDUCE(1, &|vals| {
+				if let ExprKind::IName(op, size) = vals[0].kind {
+					(Instr, Expr{
+						kind: ExprKind::Instruction(op, size, vec![]),
+						span: vals[0].span.clone(),
+						..Default::default()
+					})
+				} else {unreachable!()}}),
+
 		(IName(_), Reg(_)) |
 		(IName(_), LBR) |
 		(IName(_), Number(_)) |
 		(IName(_), TILDA) |
+		(IName(_), MINUS) |
+		(IName(_), PLUS) |
+		(IName(_), BANG) |
 		(IName(_), Id(_)) => return
-			Operation::SHIFT(vec![Reg(""), LBR, Number(0), TILDA, Id("")]),
+			Operation::SHIFT(vec![Reg(""), LBR, Number(0), TILDA, MINUS, PLUS, BANG, Id("")]),
+
+		(E2, QUESTION) => return
+			Operation::SHIFT(vec![QUESTION]),
+
+		(E2, OROR) => return
+			Operation::SHIFT(vec![OROR]),
+
+		(E3, ANDAND) => return
+			Operation::SHIFT(vec![ANDAND]),
+
+		(E4, EQ) => return
+			Operation::SHIFT(vec![EQ]),
+
+		(E4, NE) => return
+			Operation::SHIFT(vec![NE]),
+
+		(E5, LT) => return
+			Operation::SHIFT(vec![LT]),
 
-		(E1, PIPE) => return
+		(E5, LE) => return
+			Operation::SHIFT(vec![LE]),
+
+		(E5, GT) => return
+			Operation::SHIFT(vec![GT]),
+
+		(E5, GE) => return
+			Operation::SHIFT(vec![GE]),
+
+		(E6, PIPE) => return
 			Operation::SHIFT(vec![PIPE]),
 
-		(E2, CARET) => return
+		(E7, CARET) => return
 			Operation::SHIFT(vec![CARET]),
 
-		(E3, AMPERSAND) => return
+		(E8, AMPERSAND) => return
 			Operation::SHIFT(vec![AMPERSAND]),
 
-		(E4, LSHIFT) => return
+		(E9, LSHIFT) => return
 			Operation::SHIFT(vec![LSHIFT]),
 
-		(E4, RSHIFT) => return
+		(E9, RSHIFT) => return
 			Operation::SHIFT(vec![RSHIFT]),
 
-		(E5, PLUS) => return
+		(E10, PLUS) => return
 			Operation::SHIFT(vec![PLUS]),
 
-		(E5, MINUS) => return
+		(E10, MINUS) => return
 			Operation::SHIFT(vec![MINUS]),
 
-		(E6, PERCENT) => return
+		(E11, PERCENT) => return
 			Operation::SHIFT(vec![PERCENT]),
 
-		(E6, STAR) => return
+		(E11, STAR) => return
 			Operation::SHIFT(vec![STAR]),
 
-		(E6, SLASH) => return
+		(E11, SLASH) => return
 			Operation::SHIFT(vec![SLASH]),
 
 		(LBR, _) => return
-			Operation::SHIFT(vec![LBR, Number(0), TILDA, Id("")]),
+			Operation::SHIFT(vec![LBR, Number(0), TILDA, MINUS, PLUS, BANG, Id("")]),
 
 		(DataType(_), _) => return
-			Operation::SHIFT(vec![LBR, Number(0), TILDA, Id("")]),
+			Operation::SHIFT(vec![LBR, Number(0), TILDA, MINUS, PLUS, BANG, Id("")]),
+
+		(TILDA, _) |
+		(MINUS, _) |
+		(PLUS, _) |
+		(BANG, _) => return
+			Operation::SHIFT(vec![LBR, Number(0), TILDA, MINUS, PLUS, BANG, Id("")]),
+
+		(Directive(n), _) if directive_args(directive(n)).1 == 0 => return
+			Operation::REDUCE(1, &|vals| {
+				if let ExprKind::DName(name) = vals[0].kind {
+					(Dir, Expr{
+						kind: ExprKind::Directive(name, vec![]),
+						span: vals[0].span.clone(),
+						..Default::default()
+					})
+				} else {unreachable!()}
+			}),
 
-		(TILDA, _) => return
-			Operation::SHIFT(vec![LBR, Number(0), TILDA, Id("")]),
+		(Directive(_), _) => return
+			Operation::SHIFT(vec![LBR, Number(0), TILDA, MINUS, PLUS, BANG, Id("")]),
 
 		(E, _) => return
 			Operation::REDUCE(1, &|vals| {
@@ -400,9 +614,58 @@ pub fn reduce<'a>(stack: &Vec<Token<'a>>, lookahead: Token<'a>) -> Operation<'a>
 				})
 			}),
 
+		(E9, _) => return
+			Operation::REDUCE(1, &|vals| {
+				(E8, Expr{
+					kind: vals[0].kind.clone(),
+					span: vals[0].span.clone(),
+					..Default::default()
+				})
+			}),
+
+		(E10, _) => return
+			Operation::REDUCE(1, &|vals| {
+				(E9, Expr{
+					kind: vals[0].kind.clone(),
+					span: vals[0].span.clone(),
+					..Default::default()
+				})
+			}),
+
+		(E11, _) => return
+			Operation::REDUCE(1, &|vals| {
+				(E10, Expr{
+					kind: vals[0].kind.clone(),
+					span: vals[0].span.clone(),
+					..Default::default()
+				})
+			}),
+
+		(E12, _) => return
+			Operation::REDUCE(1, &|vals| {
+				(E11, Expr{
+					kind: vals[0].kind.clone(),
+					span: vals[0].span.clone(),
+					..Default::default()
+				})
+			}),
+
+		(E13, _) => return
+			Operation::REDUCE(1, &|vals| {
+				(E12, Expr{
+					kind: vals[0].kind.clone(),
+					span: vals[0].span.clone(),
+					..Default::default()
+				})
+			}),
+
+		// an identifier that starts a statement can only be assigned to
+		(Id(_), _) if stack.len() == 1 || is_statement(&stack[stack.len() - 2]) => return
+			Operation::SHIFT(vec![ASSIGN]),
+
 		(Id(_), _) => return
 			Operation::REDUCE(1, &|vals| {
-				(E7, Expr{
+				(E12, Expr{
 					kind: vals[0].kind.clone(),
 					span: vals[0].span.clone(),
 					..Default::default()
@@ -411,7 +674,7 @@ pub fn reduce<'a>(stack: &Vec<Token<'a>>, lookahead: Token<'a>) -> Operation<'a>
 
 		(Number(_), _) => return
 			Operation::REDUCE(1, &|vals| {
-				(E7, Expr{
+				(E12, Expr{
 					kind: vals[0].kind.clone(),
 					span: vals[0].span.clone(),
 					..Default::default()
@@ -433,6 +696,138 @@ pub fn reduce<'a>(stack: &Vec<Token<'a>>, lookahead: Token<'a>) -> Operation<'a>
 
 	match lookahead {
 		EOI => Operation::NOMATCH,
-		_ => Operation::SHIFT(vec![IName(""), DataType(""), Label("")]),
+		_ => Operation::SHIFT(vec![IName(""), DataType(""), Directive(""), Label(""), Id("")]),
+	}
+}
+
+
+pub fn token_value(tok: Token) -> ExprKind {
+	match tok {
+		Number(n) => ExprKind::Number(n),
+		Reg(n) => ExprKind::Reg(register(n)),
+		Label(n) => ExprKind::Label(n),
+		Id(n) => ExprKind::Id(n),
+		IName(n) => {
+			let (op, size) = instruction(n);
+			ExprKind::IName(op, size)
+		},
+		DataType(n) => ExprKind::DType(datatype(n)),
+		Directive(n) => ExprKind::DName(directive(n)),
+		_ => ExprKind::None,
 	}
 }
+
+
+// the stack holds a `?` still waiting for its `:`
+fn in_condition(stack: &[Token]) -> bool {
+	let expr = stack.iter().rev().take_while(|t| !is_statement(t));
+	expr.clone().filter(|t| **t == QUESTION).count() > expr.filter(|t| **t == COLON).count()
+}
+
+
+// `c ? a: b` is lexed with `a:` as a label, this turns the label back into
+// the operand in front of the `:`
+fn operand<'a>(name: &'a str, span: Span) -> (Token<'a>, Expr<'a>) {
+	let (token, kind) = match name.parse::<u64>() {
+		Ok(n) => (Number(n as i64), ExprKind::Number(n as i64)),
+		Err(_) => (Id(name), ExprKind::Id(name)),
+	};
+	(token, Expr{kind, span, ..Default::default()})
+}
+
+
+fn starts_statement(t: &Token) -> bool {
+	matches!(t, IName(_) | DataType(_) | Directive(_) | Label(_) | Id(_))
+}
+
+fn is_statement(t: &Token) -> bool {
+	matches!(t, Instr | Data | Dir | Label(_))
+}
+
+pub fn parse<'a>(text: &str, tokens: Vec<(Token<'a>, Expr<'a>)>) -> (Vec<Expr<'a>>, Vec<Diagnostic>) {
+	let eoi = (
+		EOI,
+		Expr{
+			span: text.len()..text.len(),
+			..Default::default()
+		},
+	);
+
+	let mut tokens: VecDeque<(Token, Expr)> = tokens.into();
+	let mut diags = vec![];
+
+	let mut stack: Vec<Token> = vec![];
+	let mut val_stack: Vec<Expr> = vec![];
+
+	loop {
+		if let Some((Label(name), e)) = tokens.front() && in_condition(&stack) {
+			let (name, e) = (*name, e.clone());
+			tokens.pop_front();
+			tokens.push_front((COLON, Expr{span: e.span.end - 1..e.span.end, ..Default::default()}));
+			tokens.push_front(operand(name, e.span.start..e.span.end - 1));
+		}
+
+		let lookahead = tokens.front().unwrap_or(&eoi);
+
+		match reduce(&stack, lookahead.0.clone()) {
+			Operation::NOMATCH => break,
+			Operation::SHIFT(expected) => {
+				if expected.iter().any(|i| std::mem::discriminant(i) == std::mem::discriminant(&lookahead.0)) {
+					let next = tokens.pop_front().unwrap();
+					stack.push(next.0);
+					val_stack.push(next.1);
+					continue;
+				}
+
+				let expected: Vec<&str> = expected.iter().map(|t| t.describe()).collect();
+				let expected = match expected.as_slice() {
+					[one] => one.to_string(),
+					_ => format!("one of {}", expected.join(", ")),
+				};
+				diags.push(Diagnostic::error(lookahead.1.span.clone(), format!(
+					"expected {expected}, found {}",
+					lookahead.0.describe(),
+				)));
+
+				// panic mode: drop the broken statement and skip to the next label
+				// or to the next instruction that starts on a new line
+				let mut keep = stack.iter().rposition(is_statement).map_or(0, |i| i + 1);
+				// a statement that was already reduced but goes on with the bad token on the
+				// same line is broken too, checking its operands would only repeat the error
+				if keep > 0 && matches!(stack[keep - 1], Instr | Data | Dir)
+					&& !text[val_stack[keep - 1].span.start..lookahead.1.span.start].contains('\n') {
+					keep -= 1;
+				}
+				let start = val_stack.get(keep).map_or(lookahead.1.span.start, |e| e.span.start);
+				stack.truncate(keep);
+				val_stack.truncate(keep);
+
+				while let Some((t, e)) = tokens.front() {
+					if matches!(t, Label(_)) ||
+						(starts_statement(t) && text[start..e.span.start].contains('\n')) {
+						break;
+					}
+					tokens.pop_front();
+				}
+			},
+			Operation::REDUCE(n, action) => {
+				stack.drain(stack.len() - n..);
+				let (t, v) = action(
+					val_stack.drain(val_stack.len() - n..).collect(),
+				);
+				stack.push(t);
+				val_stack.push(v);
+			},
+		}
+	}
+
+	if let Some(i) = stack.iter().position(|t| !is_statement(t)) {
+		diags.push(Diagnostic::error(
+			val_stack[i].span.clone(),
+			format!("unexpected {}", stack[i].describe()),
+		));
+		val_stack.truncate(i);
+	}
+
+	(val_stack, diags)
+}
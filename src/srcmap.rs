@@ -0,0 +1,36 @@
+// where every line of the text handed to the preprocessor came from, so that
+// positions can be mapped back through `#include` splicing
+#[derive(Debug, Clone, Default)]
+pub struct SourceMap {
+	pub files: Vec<String>,
+	// (file, line) for each line of the combined text
+	lines: Vec<(usize, usize)>,
+}
+
+
+impl SourceMap {
+	pub fn add_file<N: Into<String>>(&mut self, name: N) -> usize {
+		self.files.push(name.into());
+		self.files.len() - 1
+	}
+
+	pub fn push_line(&mut self, file: usize, line: usize) {
+		self.lines.push((file, line));
+	}
+
+	// file name and 1-based line in it for a 1-based line of the combined text
+	pub fn origin(&self, line: usize) -> Option<(&str, usize)> {
+		let (file, line) = *self.lines.get(line.checked_sub(1)?)?;
+		Some((&self.files[file], line))
+	}
+
+	// the map for a text whose n-th line was produced from line `lines[n]` of this one
+	pub fn remap(&self, lines: &[usize]) -> SourceMap {
+		SourceMap {
+			files: self.files.clone(),
+			lines: lines.iter()
+				.map(|l| self.lines.get(l - 1).copied().unwrap_or((0, *l)))
+				.collect(),
+		}
+	}
+}
@@ -1,8 +1,26 @@
 use logos::Logos;
 
 
+#[derive(Debug, Clone, Default, PartialEq)]
+pub enum LexError {
+	#[default]
+	Unexpected,
+	// a number literal that does not fit in 64 bits
+	Overflow,
+}
+
+
+// literals are read as u64 so that full 64-bit patterns like 0xffffffffffffffff
+// are accepted, they are kept as the same bits in an i64
+fn number(digits: &str, radix: u32) -> Result<i64, LexError> {
+	u64::from_str_radix(digits, radix).map(|n| n as i64).map_err(|_| LexError::Overflow)
+}
+
+
+#[allow(clippy::upper_case_acronyms)]
 #[derive(Logos, Debug, Clone, Eq, PartialEq)]
 #[logos(skip r"\s+")]
+#[logos(error = LexError)]
 pub enum Token<'a> {
 	#[regex(r"//.+", logos::skip)]
 	COMMENT,
@@ -35,10 +53,34 @@ pub enum Token<'a> {
 	LSHIFT,
 	#[token(">>")]
 	RSHIFT,
+	#[token("==")]
+	EQ,
+	#[token("!=")]
+	NE,
+	#[token("<")]
+	LT,
+	#[token("<=")]
+	LE,
+	#[token(">")]
+	GT,
+	#[token(">=")]
+	GE,
+	#[token("=")]
+	ASSIGN,
+	#[token("&&")]
+	ANDAND,
+	#[token("||")]
+	OROR,
+	#[token("!")]
+	BANG,
+	#[token("?")]
+	QUESTION,
+	#[token(":")]
+	COLON,
 
-	#[regex(r"[0-9]+", |lex| lex.slice().parse::<i64>().unwrap())]
-	#[regex(r"0x[0-9a-fA-F]+", |lex| i64::from_str_radix(lex.slice().strip_prefix("0x").unwrap(), 16).unwrap())]
-	#[regex(r"0b[0-1]+", |lex| i64::from_str_radix(lex.slice().strip_prefix("0b").unwrap(), 2).unwrap())]
+	#[regex(r"[0-9]+", |lex| number(lex.slice(), 10))]
+	#[regex(r"0x[0-9a-fA-F]+", |lex| number(&lex.slice()[2..], 16))]
+	#[regex(r"0b[0-1]+", |lex| number(&lex.slice()[2..], 2))]
 	Number(i64),
 
 	#[regex(r"(r[0-9]|r1[0-5]|sp|pc)")]
@@ -50,16 +92,22 @@ pub enum Token<'a> {
 	#[regex(r"(db|ds|di|dl)")]
 	DataType(&'a str),
 
-	#[regex(r"[^0-9\s\+\-\*\/\%\(\)\|\^\&\~\,][^\s\+\-\*\/\%\(\)\|\^\&\~\,]*", |lex| lex.slice())]
+	#[regex(r"\.(global|globl|extern|section|text|data|bss|org|align|balign|space|skip|fill|base|if|elseif|else|endif|ifdef|ifndef|equ|set)")]
+	Directive(&'a str),
+
+	#[regex(r"[^0-9\s\+\-\*\/\%\(\)\|\^\&\~\,<>=!?:][^\s\+\-\*\/\%\(\)\|\^\&\~\,<>=!?:]*", |lex| lex.slice())]
+	#[regex(r"[0-9]+[bf]", |lex| lex.slice())]
 	Id(&'a str),
 
-	#[regex(r"[^0-9\s\+\-\*\/\%\(\)\|\^\&\~\,][^\s\+\-\*\/\%\(\)\|\^\&\~\,]*:", |lex| lex.slice().strip_suffix(":"))]
+	#[regex(r"[^0-9\s\+\-\*\/\%\(\)\|\^\&\~\,<>=!?:][^\s\+\-\*\/\%\(\)\|\^\&\~\,<>=!?:]*:", |lex| lex.slice().strip_suffix(":"))]
+	#[regex(r"[0-9]+:", |lex| lex.slice().strip_suffix(":"))]
 	Label(&'a str),
 
 	EOI,
 
 	Vals,
 	Data,
+	Dir,
 	E,
 	E1,
 	E2,
@@ -69,5 +117,66 @@ pub enum Token<'a> {
 	E6,
 	E7,
 	E8,
+	E9,
+	E10,
+	E11,
+	E12,
+	E13,
 	Instr,
 }
+
+
+impl Token<'_> {
+	pub fn describe(&self) -> &'static str {
+		match self {
+			Token::PLUS => "`+`",
+			Token::MINUS => "`-`",
+			Token::STAR => "`*`",
+			Token::SLASH => "`/`",
+			Token::PERCENT => "`%`",
+			Token::AMPERSAND => "`&`",
+			Token::PIPE => "`|`",
+			Token::CARET => "`^`",
+			Token::TILDA => "`~`",
+			Token::LBR => "`(`",
+			Token::RBR => "`)`",
+			Token::COMMA => "`,`",
+			Token::LSHIFT => "`<<`",
+			Token::RSHIFT => "`>>`",
+			Token::EQ => "`==`",
+			Token::NE => "`!=`",
+			Token::LT => "`<`",
+			Token::LE => "`<=`",
+			Token::GT => "`>`",
+			Token::GE => "`>=`",
+			Token::ASSIGN => "`=`",
+			Token::ANDAND => "`&&`",
+			Token::OROR => "`||`",
+			Token::BANG => "`!`",
+			Token::QUESTION => "`?`",
+			Token::COLON => "`:`",
+			Token::Number(_) => "number",
+			Token::Reg(_) => "register",
+			Token::IName(_) => "instruction",
+			Token::DataType(_) => "data directive",
+			Token::Directive(_) => "directive",
+			Token::Id(_) => "identifier",
+			Token::Label(_) => "label",
+			Token::EOI => "end of input",
+			Token::Instr | Token::Data | Token::Dir => "statement",
+			_ => "expression",
+		}
+	}
+}
+
+
+// numbers in the same notations the lexer accepts, for command line arguments
+pub fn parse_number(s: &str) -> Option<i64> {
+	if let Some(hex) = s.strip_prefix("0x") {
+		number(hex, 16).ok()
+	} else if let Some(bin) = s.strip_prefix("0b") {
+		number(bin, 2).ok()
+	} else {
+		number(s, 10).ok()
+	}
+}
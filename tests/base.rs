@@ -0,0 +1,77 @@
+mod common;
+
+use rust_as::emu::{Machine, Stop, PC};
+
+use common::{assemble, dir};
+
+
+#[test]
+fn base_moves_labels() {
+	let image = assemble(&dir("base"), "
+	addn r1, r0, here
+here:
+	subn pc, pc, 12
+", &["--base", "0x100"]).unwrap();
+
+	let mut machine = Machine::new(0x1000);
+	machine.load(&image, 0x100).unwrap();
+	machine.set_reg(PC, 0x100);
+	assert_eq!(machine.run(100), Stop::Halted);
+	assert_eq!(machine.reg(1), 0x10c);
+}
+
+
+#[test]
+fn sizes_see_the_same_addresses_as_the_output() {
+	let image = assemble(&dir("base-sizes"), "
+start:
+	db 1
+	.space 0x110 - $
+next:
+	dl next, $
+	.org next - start + 0x10
+	dl $
+", &["--base", "0x100"]).unwrap();
+
+	assert_eq!(image.len(), 0x28);
+	assert_eq!(&image[0x10..0x18], &0x110u64.to_le_bytes());
+	assert_eq!(&image[0x18..0x20], &0x110u64.to_le_bytes());
+	assert_eq!(&image[0x20..0x28], &0x120u64.to_le_bytes());
+}
+
+
+#[test]
+fn sizes_see_the_base_of_later_sections() {
+	let image = assemble(&dir("base-sections"), "
+	dl table
+.data
+.base 0x2000
+table:
+	db 1
+	.space 0x2010 - $
+	dl $
+.text
+	.space 0x1020 - $
+	dl $
+", &["--base", "0x1000"]).unwrap();
+
+	// .text is 0x28 bytes, .data keeps its place in the file but not its address
+	assert_eq!(&image[..8], &0x2000u64.to_le_bytes());
+	assert_eq!(&image[0x20..0x28], &0x1020u64.to_le_bytes());
+	assert_eq!(&image[0x28 + 0x10..], &0x2010u64.to_le_bytes());
+}
+
+
+#[test]
+fn section_addresses_that_never_settle_are_reported() {
+	// .data follows .text, which is only large when .data starts early
+	let error = assemble(&dir("base-settle"), "
+.data
+table:
+	db 1
+.text
+	.space 0x10 - (table & 0x10)
+", &[]).unwrap_err();
+
+	assert!(error.contains("section addresses do not settle after 16 passes"), "{error}");
+}
@@ -0,0 +1,49 @@
+// helpers shared by the integration tests, not every test uses all of them
+#![allow(dead_code)]
+
+use std::path::{Path, PathBuf};
+use std::process::Command;
+
+use rust_as::emu::{Machine, Stop};
+
+
+// an empty directory for the files of one test
+pub fn dir(test: &str) -> PathBuf {
+	let dir = std::env::temp_dir().join(format!("rust_as-{test}-{}", std::process::id()));
+	let _ = std::fs::remove_dir_all(&dir);
+	std::fs::create_dir_all(&dir).unwrap();
+	dir
+}
+
+
+pub fn write(dir: &Path, name: &str, text: &str) {
+	std::fs::write(dir.join(name), text).unwrap();
+}
+
+
+// runs one of the binaries in `dir`, returns its stderr when it fails
+pub fn run(bin: &str, dir: &Path, args: &[&str]) -> Result<(), String> {
+	let output = Command::new(bin).current_dir(dir).args(args).output().unwrap();
+	if output.status.success() {
+		Ok(())
+	} else {
+		Err(String::from_utf8_lossy(&output.stderr).into_owned())
+	}
+}
+
+
+// assembles `source` into a flat image with the extra `args`
+pub fn assemble(dir: &Path, source: &str, args: &[&str]) -> Result<Vec<u8>, String> {
+	write(dir, "input.S", source);
+	run(env!("CARGO_BIN_EXE_rust_as"), dir, &[&["input.S", "output.bin"], args].concat())?;
+	Ok(std::fs::read(dir.join("output.bin")).unwrap())
+}
+
+
+// loads an image at 0 and runs it until it stops
+pub fn emulate(image: &[u8]) -> (Machine, Stop) {
+	let mut machine = Machine::new(0x10000);
+	machine.load(image, 0).unwrap();
+	let stop = machine.run(10000);
+	(machine, stop)
+}
@@ -0,0 +1,104 @@
+mod common;
+
+use rust_as::emu::Stop;
+
+use common::{assemble, dir, emulate};
+
+
+#[test]
+fn constants_and_conditions_see_later_definitions() {
+	let image = assemble(&dir("forward"), "
+.equ COUNT, (last - first) / 8
+	addn r1, r0, COUNT
+	addn r2, r0, LIMIT * 2
+.if COUNT > 2
+	addn r3, r0, 1
+.elseif COUNT > 1
+	addn r3, r0, 2
+.else
+	addn r3, r0, 3
+.endif
+.ifdef MISSING
+	addn r4, r0, 1
+.endif
+.ifndef LIMIT
+	addn r4, r0, 2
+.endif
+	subn pc, pc, 12
+first:
+	dl 1, 2, 3
+last:
+LIMIT = 21
+", &[]).unwrap();
+
+	let (machine, stop) = emulate(&image);
+	assert_eq!(stop, Stop::Halted);
+	assert_eq!(machine.reg(1), 3);
+	assert_eq!(machine.reg(2), 42);
+	assert_eq!(machine.reg(3), 1);
+	assert_eq!(machine.reg(4), 0);
+}
+
+
+#[test]
+fn condition_on_code_size_settles() {
+	// the block grows the code it measures, so it takes more than one layout
+	let image = assemble(&dir("settle"), "
+start:
+.if end - start < 64
+	addn r1, r1, 1
+	addn r1, r1, 1
+	addn r1, r1, 1
+.endif
+end:
+	subn pc, pc, 12
+", &[]).unwrap();
+
+	let (machine, stop) = emulate(&image);
+	assert_eq!(stop, Stop::Halted);
+	assert_eq!(machine.reg(1), 3);
+}
+
+
+#[test]
+fn conditions_that_never_settle_are_reported() {
+	// the block is only kept while it is not there
+	let error = assemble(&dir("flip"), "
+start:
+.if end - start == 0
+	db 1
+.endif
+end:
+", &[]).unwrap_err();
+
+	assert!(error.contains("conditional assembly does not settle after 16 passes\n --> input.S:3:1"), "{error}");
+}
+
+
+#[test]
+fn undefined_constant_is_reported() {
+	let error = assemble(&dir("undefined"), "
+.if MISSING
+	addn r1, r0, 1
+.endif
+", &[]).unwrap_err();
+
+	assert!(error.contains("MISSING"), "{error}");
+}
+
+
+#[test]
+fn unbalanced_blocks_are_reported() {
+	let error = assemble(&dir("unbalanced"), "
+.else
+.endif
+.if 1
+.else
+.elseif 1
+", &[]).unwrap_err();
+
+	assert!(error.contains("`.else` without `.if`"), "{error}");
+	assert!(error.contains("`.endif` without `.if`"), "{error}");
+	assert!(error.contains("`.elseif` after `.else`"), "{error}");
+	assert!(error.contains("`.if` without `.endif`\n --> input.S:4:1"), "{error}");
+}
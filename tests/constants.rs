@@ -0,0 +1,80 @@
+mod common;
+
+use rust_as::object::Object;
+
+use common::{assemble, dir, run, write};
+
+
+#[test]
+fn constants_may_use_labels_and_later_definitions() {
+	let dir = dir("constants");
+	let image = assemble(&dir, "
+.set STEP, 1
+	db STEP
+.set STEP, STEP + 1
+	db STEP
+	dl TOP
+TOP = stack + 16
+SIZE = 4 * 4
+.data
+stack:
+	.space SIZE
+", &["-M", "out.map"]).unwrap();
+
+	// .set gives the value defined last before the use
+	assert_eq!(&image[..8], [1, 0, 0, 0, 2, 0, 0, 0]);
+	assert_eq!(&image[8..16], &32u64.to_le_bytes());
+	assert_eq!(image.len(), 32);
+
+	let map = std::fs::read_to_string(dir.join("out.map")).unwrap();
+	assert_eq!(map, "\
+0000000000000002 *ABS* STEP
+0000000000000010 *ABS* SIZE
+0000000000000010 .data stack
+0000000000000020 .data TOP
+");
+}
+
+
+#[test]
+fn constants_are_exported_from_objects() {
+	let dir = dir("constants-object");
+	write(&dir, "input.S", "
+.global TOP, SIZE
+	dl TOP
+TOP = stack + 16
+SIZE = 4 * 4
+.data
+stack:
+	.space SIZE
+");
+	run(env!("CARGO_BIN_EXE_rust_as"), &dir, &["input.S", "output.o", "-c"]).unwrap();
+	let object = Object::read(&std::fs::read(dir.join("output.o")).unwrap()).unwrap();
+
+	let symbol = |name| object.symbols.iter().find(|s| s.name == name).unwrap();
+	let data = object.sections.iter().position(|s| s.name == ".data");
+
+	let top = symbol("TOP");
+	assert_eq!((top.section, top.value, top.global, top.absolute), (data, 16, true, false));
+	let size = symbol("SIZE");
+	assert_eq!((size.section, size.value, size.global, size.absolute), (None, 16, true, true));
+}
+
+
+#[test]
+fn bad_constants_are_reported() {
+	let error = assemble(&dir("constants-errors"), "
+.equ A, 1
+.equ A, 2
+.set B, 1
+.equ B, 2
+C = C + 1
+start:
+start = 4
+", &[]).unwrap_err();
+
+	assert!(error.contains("`A` is already defined\n --> input.S:3:6"), "{error}");
+	assert!(error.contains("`B` is already defined\n --> input.S:5:6"), "{error}");
+	assert!(error.contains("`C` is defined in terms of itself"), "{error}");
+	assert!(error.contains("`start` is already defined\n --> input.S:8:1"), "{error}");
+}
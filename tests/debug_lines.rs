@@ -0,0 +1,39 @@
+mod common;
+
+use rust_as::listing::read_debug_lines;
+
+use common::{assemble, dir, run, write};
+
+
+#[test]
+fn instructions_map_back_to_their_files() {
+	let dir = dir("debug-lines");
+	write(&dir, "helper.inc", "\taddn r2, r0, 2\n\n\taddn r3, r0, 3\n");
+	assemble(&dir, "\
+	addn r1, r0, 1
+#include \"helper.inc\"
+	dl 0
+	subn pc, pc, 12
+", &["--base", "0x100", "-g", "out.dbg"]).unwrap();
+
+	let text = std::fs::read_to_string(dir.join("out.dbg")).unwrap();
+	assert!(text.starts_with("0000000000000100 input.S:1\n000000000000010c helper.inc:1\n"), "{text}");
+
+	// data is left out, only instructions get a line
+	assert_eq!(read_debug_lines(&text).unwrap(), [
+		(0x100, "input.S".to_string(), 1),
+		(0x10c, "helper.inc".to_string(), 1),
+		(0x118, "helper.inc".to_string(), 3),
+		(0x12c, "input.S".to_string(), 4),
+	]);
+}
+
+
+#[test]
+fn debug_lines_need_a_flat_image() {
+	let dir = dir("debug-lines-object");
+	write(&dir, "input.S", "\taddn r1, r0, 1\n");
+	let error = run(env!("CARGO_BIN_EXE_rust_as"), &dir, &["input.S", "output.o", "-c", "-g", "out.dbg"])
+		.unwrap_err();
+	assert!(error.contains("`-g` cannot be used with `-c`"), "{error}");
+}
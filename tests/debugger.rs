@@ -0,0 +1,135 @@
+mod common;
+
+use rust_as::debug::Debugger;
+use rust_as::emu::Machine;
+use rust_as::listing::{read_listing, read_map};
+
+use common::{assemble, dir, write};
+
+
+// assembles `source` and loads it into a debugger with its symbol map and listing
+fn debugger(test: &str, source: &str) -> Debugger {
+	let dir = dir(test);
+	let image = assemble(&dir, source, &["-M", "out.map", "-l", "out.lst"]).unwrap();
+	let map = std::fs::read_to_string(dir.join("out.map")).unwrap();
+	let listing = std::fs::read_to_string(dir.join("out.lst")).unwrap();
+
+	let mut machine = Machine::new(0x1000);
+	machine.load(&image, 0).unwrap();
+	Debugger::new(machine, read_map(&map).unwrap(), read_listing(&listing).unwrap())
+}
+
+
+// runs a command that is expected to succeed
+fn run(debugger: &mut Debugger, line: &str) -> String {
+	debugger.command(line).unwrap().unwrap()
+}
+
+
+const COUNTER: &str = "
+start:
+	addn r1, r0, 0
+loop:
+	addn r1, r1, 1
+	sto r1, r0, counter
+	subn r2, r1, 3
+	addz pc, r0, done
+	addn pc, r0, loop
+done:
+	subn pc, pc, 12
+counter:
+	dl 0
+";
+
+
+#[test]
+fn breakpoints_and_stepping() {
+	let mut debugger = debugger("debugger-break", COUNTER);
+
+	assert_eq!(run(&mut debugger, "break loop"), "breakpoint 0 at loop\n");
+	assert_eq!(run(&mut debugger, "continue"), "\
+breakpoint 0 at loop
+000000000000000c <loop>  addnL r1, r1, 0x1
+    5  addn r1, r1, 1
+");
+
+	assert_eq!(run(&mut debugger, "step 2"), "\
+0000000000000024 <loop+0x18>  subnL r2, r1, 0x3
+    7  subn r2, r1, 3
+");
+	assert_eq!(debugger.machine.reg(1), 1);
+
+	run(&mut debugger, "continue");
+	assert_eq!(debugger.machine.reg(1), 1);
+	assert_eq!(debugger.machine.reg(15), 0xc);
+
+	assert_eq!(run(&mut debugger, "delete 0"), "");
+	assert!(run(&mut debugger, "continue").starts_with("program halted\n0000000000000048 <done>"));
+	assert_eq!(debugger.machine.reg(1), 3);
+	assert!(run(&mut debugger, "step").starts_with("program halted\n"));
+
+	assert_eq!(run(&mut debugger, "x counter 8"), "0000000000000054  03 00 00 00 00 00 00 00\n");
+	let regs = run(&mut debugger, "regs");
+	assert!(regs.contains("  r1 0000000000000003\n"), "{regs}");
+	assert!(regs.contains("  pc 0000000000000048\n"), "{regs}");
+	assert!(regs.ends_with("mode kernel\n"), "{regs}");
+}
+
+
+#[test]
+fn watchpoints_stop_on_changes() {
+	let mut debugger = debugger("debugger-watch", COUNTER);
+
+	assert_eq!(run(&mut debugger, "watch counter"), "watchpoint 0 at counter\n");
+	assert!(run(&mut debugger, "continue").starts_with("watchpoint 0 at 0x54: 0x0 -> 0x1\n"));
+	assert!(run(&mut debugger, "continue").starts_with("watchpoint 0 at 0x54: 0x1 -> 0x2\n"));
+
+	run(&mut debugger, "break done");
+	assert_eq!(run(&mut debugger, "info"), "breakpoint 0 at done\nwatchpoint 0 at counter (8 bytes)\n");
+
+	assert_eq!(run(&mut debugger, "unwatch 0"), "");
+	assert!(run(&mut debugger, "continue").starts_with("breakpoint 0 at done\n"));
+	assert_eq!(debugger.machine.reg(1), 3);
+}
+
+
+#[test]
+fn bad_commands_are_reported() {
+	let mut debugger = debugger("debugger-errors", COUNTER);
+
+	let error = |debugger: &mut Debugger, line| debugger.command(line).unwrap().unwrap_err();
+	assert_eq!(error(&mut debugger, "break nowhere"), "unknown label or address `nowhere`");
+	assert_eq!(error(&mut debugger, "watch counter 3"), "watchpoint size must be 1, 2, 4 or 8, found 3");
+	assert_eq!(error(&mut debugger, "watch 0x2000"), "8-byte access at 0x2000 is outside of memory");
+	assert_eq!(error(&mut debugger, "delete 3"), "no breakpoint 3");
+	assert_eq!(error(&mut debugger, "unwatch 3"), "no watchpoint 3");
+	assert_eq!(error(&mut debugger, "frobnicate"), "unknown command `frobnicate`, try `help`");
+	assert!(debugger.command("quit").is_none());
+}
+
+
+#[test]
+fn continue_stops_at_the_step_limit() {
+	let mut debugger = debugger("debugger-limit", "
+loop:
+	addn r1, r1, 1
+	addn pc, r0, loop
+");
+	debugger.step_limit = 10;
+
+	assert!(run(&mut debugger, "continue").starts_with("step limit of 10 reached\n"));
+	assert_eq!(debugger.machine.steps, 10);
+	assert_eq!(debugger.machine.reg(1), 5);
+}
+
+
+#[test]
+fn memory_size_is_limited() {
+	let dir = dir("debugger-memory");
+	write(&dir, "image.bin", "");
+
+	for bin in [env!("CARGO_BIN_EXE_rust_dbg"), env!("CARGO_BIN_EXE_rust_emu")] {
+		let error = common::run(bin, &dir, &["image.bin", "--memory", "0x100000001"]).unwrap_err();
+		assert!(error.contains("memory size 0x100000001 is larger than 0x100000000 bytes"), "{error}");
+	}
+}
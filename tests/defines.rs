@@ -0,0 +1,53 @@
+mod common;
+
+use std::process::Command;
+
+use common::{assemble, dir, write};
+
+
+#[test]
+fn command_line_defines() {
+	let image = assemble(&dir("defines"), "\
+	db SIZE, VALUE
+#ifdef FLAG
+	db 0xf
+#endif
+#ifdef __RUST_AS__
+	db 0xee
+#endif
+", &["-D", "SIZE=3", "-DFLAG", "-DVALUE=SIZE * 2", "-U", "__RUST_AS__"]).unwrap();
+
+	assert_eq!(image, [3, 6, 0, 0, 0xf, 0, 0, 0]);
+}
+
+
+#[test]
+fn builtin_macros() {
+	let image = assemble(&dir("builtins"), "\
+	db __LINE__, __COUNTER__, __COUNTER__
+
+	db __LINE__, __COUNTER__
+#if __RUST_AS__ && __RUST_AS_VERSION__ > 0
+	db 1
+#endif
+", &[]).unwrap();
+
+	assert_eq!(image, [1, 0, 1, 0, 3, 2, 0, 0, 1, 0, 0, 0]);
+}
+
+
+#[test]
+fn file_and_date_builtins() {
+	// strings are not numbers, the expanded line shows up in the error
+	let dir = dir("builtin-strings");
+	write(&dir, "input.S", "\tdb __FILE__, __DATE__, __TIME__\n");
+	let output = Command::new(env!("CARGO_BIN_EXE_rust_as"))
+		.current_dir(&dir)
+		.args(["input.S", "output.bin"])
+		.env("SOURCE_DATE_EPOCH", "86399")
+		.output()
+		.unwrap();
+
+	let error = String::from_utf8_lossy(&output.stderr);
+	assert!(error.contains("1 | \tdb \"input.S\", \"Jan  1 1970\", \"23:59:59\"\n"), "{error}");
+}
@@ -0,0 +1,95 @@
+mod common;
+
+use common::{assemble, dir};
+
+
+#[test]
+fn unknown_label_points_at_its_use() {
+	let dir = dir("unknown-label");
+	let error = assemble(&dir, "
+	addn r1, r0, 1
+	addn r2, r0, missing + 4
+", &[]).unwrap_err();
+
+	assert!(error.contains("error: label `missing` not found"), "{error}");
+	assert!(error.contains(" --> input.S:3:15"), "{error}");
+	assert!(error.contains("3 | \taddn r2, r0, missing + 4"), "{error}");
+	assert!(error.contains("^^^^^^^"), "{error}");
+	assert!(!dir.join("output.bin").exists());
+}
+
+
+#[test]
+fn number_literals_cover_64_bits() {
+	let image = assemble(&dir("numbers"), "\
+	dl 0xffffffffffffffff, 18446744073709551615
+	dl 0b1000000000000000000000000000000000000000000000000000000000000001
+", &[]).unwrap();
+
+	assert_eq!(&image[..16], &[0xff; 16]);
+	assert_eq!(&image[16..24], &0x8000000000000001u64.to_le_bytes());
+}
+
+
+#[test]
+fn overflowing_number_literals_are_reported() {
+	let error = assemble(&dir("overflow"), "\
+	dl 0x10000000000000000
+	dl 99999999999999999999
+", &[]).unwrap_err();
+
+	assert!(error.contains("number `0x10000000000000000` does not fit in 64 bits"), "{error}");
+	assert!(error.contains("number `99999999999999999999` does not fit in 64 bits"), "{error}");
+	assert_eq!(error.matches("error:").count(), 2, "{error}");
+}
+
+
+#[test]
+fn division_errors_are_told_apart() {
+	let error = assemble(&dir("division"), "\
+	dl 1 / 0
+	dl 1 % (2 - 2)
+	dl (-0x7fffffffffffffff - 1) / -1
+", &[]).unwrap_err();
+
+	assert_eq!(error.matches("division by zero").count(), 2, "{error}");
+	assert!(error.contains("division overflows"), "{error}");
+	assert!(error.contains(" --> input.S:3:5"), "{error}");
+}
+
+
+#[test]
+fn missing_input_is_reported() {
+	let error = common::run(env!("CARGO_BIN_EXE_rust_as"), &dir("missing-input"), &["missing.S", "out.bin"])
+		.unwrap_err();
+	assert!(error.contains("missing.S"), "{error}");
+}
+
+
+#[test]
+fn every_syntax_error_is_reported() {
+	let error = assemble(&dir("syntax-errors"), "\
+	addn r1, , 4
+	addn r2, r0, 1
+	dl 1, , 2
+start:
+	addn r3, r0, (1 + 2
+	chst r0
+", &[]).unwrap_err();
+
+	assert_eq!(error.matches("error:").count(), 3, "{error}");
+	assert!(error.contains(" --> input.S:1:10"), "{error}");
+	assert!(error.contains(" --> input.S:3:8"), "{error}");
+	assert!(error.contains(" --> input.S:6:2"), "{error}");
+	assert!(error.contains("expected one of register, `(`, number"), "{error}");
+}
+
+
+#[test]
+fn broken_statement_is_reported_once() {
+	for source in ["\taddn r1 ,,\n", "\taddn r1 )\n", "\taddn r1, r2, 3 4\n"] {
+		let error = assemble(&dir("broken-statement"), source, &[]).unwrap_err();
+		assert_eq!(error.matches("error:").count(), 1, "{error}");
+		assert!(!error.contains("operands"), "{error}");
+	}
+}
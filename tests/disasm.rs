@@ -0,0 +1,49 @@
+mod common;
+
+use rust_as::disasm::{decode, disassemble};
+
+use common::{assemble, dir};
+
+
+#[test]
+fn disassembly_shows_sizes_and_labels() {
+	let image = assemble(&dir("disasm"), "
+start:
+	addnB r1, r0, 1
+	addnS r2, sp, start
+	addI r3, r1, r2
+	loa pc, r0, start
+	iint 0x2a
+", &["--base", "0x100"]).unwrap();
+
+	assert_eq!(disassemble(&image, 0x100, &[(0x100, "start".to_string())]), "\
+start:
+0000000000000100:  06 01 00 00 01 00 00 00 00 00 00 00  addnB r1, r0, 0x1
+000000000000010c:  06 e2 00 10 00 01 00 00 00 00 00 00  addnS r2, sp, start
+0000000000000118:  02 13 02 20                          addI r3, r1, r2
+000000000000011c:  01 0f 00 30 00 01 00 00 00 00 00 00  loaL pc, r0, start
+0000000000000128:  1b 00 20 3a                          iintL 0x2a
+");
+}
+
+
+#[test]
+fn invalid_encodings_are_shown_as_data() {
+	// an unknown opcode, a size nibble past L and a truncated instruction
+	let image = [0x27, 0, 0, 0, 0x02, 0x13, 0x02, 0x40, 0x06, 0x01, 0x00, 0x30, 1, 0];
+	assert_eq!(decode(&image[4..]), None);
+
+	assert_eq!(disassemble(&image, 0, &[]), "\
+0000000000000000:  27 00 00 00                          db 0x27, 0x00, 0x00, 0x00
+0000000000000004:  02 13 02 40                          db 0x02, 0x13, 0x02, 0x40
+0000000000000008:  06 01 00 30                          db 0x06, 0x01, 0x00, 0x30
+000000000000000c:  01 00                                db 0x01, 0x00
+");
+}
+
+
+#[test]
+fn addresses_wrap_at_the_top_of_memory() {
+	let text = disassemble(&[0x02, 0x13, 0x02, 0x20, 0x02, 0x13, 0x02, 0x20], u64::MAX - 3, &[]);
+	assert!(text.ends_with("0000000000000000:  02 13 02 20                          addI r3, r1, r2\n"), "{text}");
+}
@@ -0,0 +1,105 @@
+mod common;
+
+use rust_as::emu::{Fault, Machine, Stop, FLAG_CARRY, FLAG_SIGN, FLAG_ZERO, PC};
+
+use common::{assemble, dir, emulate};
+
+
+#[test]
+fn loop_runs_to_halt() {
+	let image = assemble(&dir("loop"), "
+	addn r1, r0, 0
+	addn r2, r0, 4
+loop:
+	add r1, r1, r2
+	subn r2, r2, 1
+	addz pc, r0, done
+	addn pc, r0, loop
+done:
+	loa r3, r0, value
+	subn pc, pc, 12
+value:
+	dl 0x1122334455667788
+", &[]).unwrap();
+
+	let (machine, stop) = emulate(&image);
+	assert_eq!(stop, Stop::Halted);
+	assert_eq!(machine.reg(1), 4 + 3 + 2 + 1);
+	assert_eq!(machine.reg(2), 0);
+	assert_eq!(machine.reg(3), 0x1122334455667788);
+	assert_eq!(machine.reg(PC), 0x4c);
+}
+
+
+#[test]
+fn sized_arithmetic_wraps_and_sets_flags() {
+	let image = assemble(&dir("sized"), "
+	addnB r1, r0, 0xff
+	addnB r1, r1, 1
+	loflag r2
+	addnB r3, r0, 0x80
+	loflag r4
+	subn pc, pc, 12
+", &[]).unwrap();
+
+	let (machine, stop) = emulate(&image);
+	assert_eq!(stop, Stop::Halted);
+	assert_eq!(machine.reg(1), 0);
+	assert_eq!(machine.reg(2) & (FLAG_ZERO | FLAG_CARRY | FLAG_SIGN), FLAG_ZERO | FLAG_CARRY);
+	assert_eq!(machine.reg(4) & (FLAG_ZERO | FLAG_CARRY | FLAG_SIGN), FLAG_SIGN);
+}
+
+
+#[test]
+fn stores_and_loads_go_through_memory() {
+	let image = assemble(&dir("memory"), "
+	addn r1, r0, 0x1234
+	sto r1, r0, 0x800
+	loaS r2, r0, 0x800
+	subn pc, pc, 12
+", &[]).unwrap();
+
+	let (machine, stop) = emulate(&image);
+	assert_eq!(stop, Stop::Halted);
+	assert_eq!(machine.read(0x800, 3).unwrap(), 0x1234);
+	assert_eq!(machine.reg(2), 0x1234);
+}
+
+
+#[test]
+fn faults_stop_at_the_instruction() {
+	let image = assemble(&dir("divide"), "
+	addn r1, r0, 1
+	divn r1, r1, 0
+", &[]).unwrap();
+	assert_eq!(emulate(&image).1, Stop::Fault { pc: 12, fault: Fault::DivisionByZero });
+
+	let image = assemble(&dir("out-of-memory"), "
+	loa r1, r0, 0x100000
+", &[]).unwrap();
+	assert!(matches!(emulate(&image).1, Stop::Fault { pc: 0, fault: Fault::Memory { .. } }));
+
+	let image = assemble(&dir("privileged"), "
+	chst r0
+", &[]).unwrap();
+	let mut machine = Machine::new(0x1000);
+	machine.load(&image, 0).unwrap();
+	machine.user = true;
+	assert_eq!(machine.run(10), Stop::Fault { pc: 0, fault: Fault::Privileged });
+}
+
+
+#[test]
+fn step_limit_stops_endless_loops() {
+	let image = assemble(&dir("endless"), "
+loop:
+	addn r1, r1, 1
+	addn pc, r0, loop
+", &[]).unwrap();
+
+	let mut machine = Machine::new(0x1000);
+	machine.load(&image, 0).unwrap();
+	assert_eq!(machine.run(100), Stop::StepLimit);
+	assert_eq!(machine.reg(1), 50);
+	assert_eq!(machine.steps, 100);
+}
@@ -0,0 +1,70 @@
+mod common;
+
+use common::{assemble, dir};
+
+
+fn values(image: &[u8]) -> Vec<i64> {
+	image.chunks(8).map(|c| i64::from_le_bytes(c.try_into().unwrap())).collect()
+}
+
+
+#[test]
+fn binary_operators_have_precedence_and_associate_left() {
+	let image = assemble(&dir("precedence"), "
+	dl 1 + 2 * 3, (1 + 2) * 3
+	dl 10 - 4 - 3, 64 / 4 / 2, 17 % 7 % 2
+	dl 1 << 2 + 1, 6 & 3 ^ 1 | 8
+	dl 1 | 2 < 4, 1 < 2 == 1, 1 == 1 && 0 || 1
+", &[]).unwrap();
+
+	assert_eq!(values(&image), [7, 9, 3, 8, 1, 8, 11, 1, 1, 1]);
+}
+
+
+#[test]
+fn unary_operators() {
+	let image = assemble(&dir("unary"), "
+	dl -1, - -2, +3, -(2 + 3) * 2
+	dl ~0, !0, !5, !!5
+", &[]).unwrap();
+
+	assert_eq!(values(&image), [-1, 2, 3, -10, -1, 1, 0, 1]);
+}
+
+
+#[test]
+fn comparisons_are_signed_and_give_zero_or_one() {
+	let image = assemble(&dir("comparisons"), "
+	dl -1 < 0, 2 <= 2, 3 > 4, 4 >= 5, 1 == 2, 1 != 2
+", &[]).unwrap();
+
+	assert_eq!(values(&image), [1, 1, 0, 0, 0, 1]);
+}
+
+
+#[test]
+fn conditionals_only_evaluate_what_they_need() {
+	let image = assemble(&dir("conditional"), "
+	dl 1 ? 2 : 3, 0 ? 2 : 3, 0 ? 1 : 0 ? 2 : 3
+	dl 0 && missing, 1 || missing, 1 ? 4 : missing
+	dl 2 && 3, 0 || 0
+", &[]).unwrap();
+
+	assert_eq!(values(&image), [2, 3, 3, 0, 1, 4, 1, 0]);
+}
+
+
+#[test]
+fn operators_on_relocatable_labels_are_checked() {
+	let error = assemble(&dir("label-operators"), "
+start:
+	dl -start
+	dl start ? 1 : 2
+", &["-c"]).unwrap_err();
+
+	assert!(error.contains("expression is not relocatable\n --> input.S:3:5"), "{error}");
+	assert!(error.contains("expected a constant expression\n --> input.S:4:5"), "{error}");
+
+	let error = assemble(&dir("taken-branch"), "\tdl 1 ? missing : 2\n", &[]).unwrap_err();
+	assert!(error.contains("label `missing` not found\n --> input.S:1:9"), "{error}");
+}
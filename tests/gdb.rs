@@ -0,0 +1,134 @@
+mod common;
+
+use std::io::{Read, Write};
+use std::net::{TcpListener, TcpStream};
+
+use rust_as::emu::Machine;
+use rust_as::gdb::Stub;
+
+use common::{assemble, dir};
+
+
+fn checksum(data: &[u8]) -> u8 {
+	data.iter().fold(0u8, |sum, b| sum.wrapping_add(*b))
+}
+
+
+fn byte(stream: &mut TcpStream) -> u8 {
+	let mut byte = [0];
+	stream.read_exact(&mut byte).unwrap();
+	byte[0]
+}
+
+
+// sends a packet, checks that it is acknowledged and returns the checked reply
+fn exchange(stream: &mut TcpStream, packet: &str) -> String {
+	write!(stream, "${packet}#{:02x}", checksum(packet.as_bytes())).unwrap();
+	assert_eq!(byte(stream), b'+', "`{packet}` was not acknowledged");
+
+	assert_eq!(byte(stream), b'$');
+	let mut data = vec![];
+	loop {
+		match byte(stream) {
+			b'#' => break,
+			b => data.push(b),
+		}
+	}
+
+	let sum = [byte(stream), byte(stream)];
+	let sum = u8::from_str_radix(std::str::from_utf8(&sum).unwrap(), 16).unwrap();
+	assert_eq!(sum, checksum(&data), "bad checksum in the reply to `{packet}`");
+
+	stream.write_all(b"+").unwrap();
+	String::from_utf8(data).unwrap()
+}
+
+
+// the value of register `n` in the reply to `g`
+fn register(registers: &str, n: usize) -> u64 {
+	let bytes: Vec<u8> = (0..8)
+		.map(|i| u8::from_str_radix(&registers[n * 16 + i * 2..][..2], 16).unwrap())
+		.collect();
+	u64::from_le_bytes(bytes.try_into().unwrap())
+}
+
+
+#[test]
+fn session() {
+	let image = assemble(&dir("gdb"), "
+	addn r1, r0, 5
+stop:
+	addn r2, r1, 1
+	subn pc, pc, 12
+", &[]).unwrap();
+
+	let mut machine = Machine::new(0x1000);
+	machine.load(&image, 0).unwrap();
+
+	let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+	let address = listener.local_addr().unwrap();
+	let server = std::thread::spawn(move || Stub::new(machine).serve(&listener));
+
+	let mut stream = TcpStream::connect(address).unwrap();
+
+	let supported = exchange(&mut stream, "qSupported:multiprocess+;swbreak+");
+	assert!(supported.contains("PacketSize="), "{supported}");
+	assert!(supported.contains("qXfer:features:read+"), "{supported}");
+
+	let registers = exchange(&mut stream, "g");
+	assert_eq!(registers.len(), 16 * 16);
+	assert_eq!(register(&registers, 14), 0x1000);
+	assert_eq!(register(&registers, 15), 0);
+
+	let memory = exchange(&mut stream, "m0,4");
+	let expected: String = image[..4].iter().map(|b| format!("{b:02x}")).collect();
+	assert_eq!(memory, expected);
+
+	assert_eq!(exchange(&mut stream, "Z0,c,4"), "OK");
+	assert_eq!(exchange(&mut stream, "c"), "S05");
+
+	let registers = exchange(&mut stream, "g");
+	assert_eq!(register(&registers, 1), 5);
+	assert_eq!(register(&registers, 2), 0);
+	assert_eq!(register(&registers, 15), 0xc);
+
+	assert_eq!(exchange(&mut stream, "z0,c,4"), "OK");
+	assert_eq!(exchange(&mut stream, "c"), "W00");
+	assert_eq!(register(&exchange(&mut stream, "g"), 2), 6);
+
+	// a packet with a wrong checksum is refused and not answered
+	stream.write_all(b"$g#00").unwrap();
+	assert_eq!(byte(&mut stream), b'-');
+
+	write!(stream, "$k#{:02x}", checksum(b"k")).unwrap();
+	assert_eq!(byte(&mut stream), b'+');
+	server.join().unwrap().unwrap();
+}
+
+
+#[test]
+fn bad_requests_do_not_stop_the_stub() {
+	let mut machine = Machine::new(0x1000);
+	machine.load(&[], 0).unwrap();
+
+	let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+	let address = listener.local_addr().unwrap();
+	let server = std::thread::spawn(move || Stub::new(machine).serve(&listener));
+
+	let mut stream = TcpStream::connect(address).unwrap();
+
+	let xml = exchange(&mut stream, "qXfer:features:read:target.xml:0,fffffffffffffff0");
+	assert!(xml.starts_with("l<?xml"), "{xml}");
+	assert_eq!(exchange(&mut stream, "qXfer:features:read:target.xml:ffffffffffffffff,ffffffffffffffff"), "l");
+
+	// every refused packet is resent by the client, however many there are
+	for _ in 0..10000 {
+		stream.write_all(b"$g#00").unwrap();
+		assert_eq!(byte(&mut stream), b'-');
+	}
+	assert_eq!(exchange(&mut stream, "qXfer:features:read:target.xml:0,0"), "m");
+
+	write!(stream, "$k#{:02x}", checksum(b"k")).unwrap();
+	assert_eq!(byte(&mut stream), b'+');
+	server.join().unwrap().unwrap();
+}
@@ -0,0 +1,102 @@
+mod common;
+
+use std::process::Command;
+
+use rust_as::minipre::{process_file, Context};
+
+use common::{assemble, dir, write};
+
+
+#[test]
+fn pragma_once_headers_may_include_each_other() {
+	let dir = dir("pragma-once");
+	write(&dir, "a.h", "#pragma once\n#include \"b.h\"\nA\n");
+	write(&dir, "b.h", "#pragma once\n#include \"a.h\"\nB\n");
+	write(&dir, "main.S", "#include \"a.h\"\n#include \"b.h\"\n#include \"a.h\"\nmain\n");
+
+	assert_eq!(process_file(dir.join("main.S"), &mut Context::new()).unwrap(), "B\nA\nmain\n");
+}
+
+
+#[test]
+fn include_cycle_names_the_files() {
+	let dir = dir("include-cycle");
+	write(&dir, "a.h", "#include \"b.h\"\n");
+	write(&dir, "b.h", "#include \"a.h\"\n");
+	write(&dir, "main.S", "#include \"a.h\"\n");
+
+	let error = process_file(dir.join("main.S"), &mut Context::new()).unwrap_err().to_string();
+	assert!(error.contains("include cycle"), "{error}");
+	assert!(error.contains("a.h -> "), "{error}");
+}
+
+
+#[test]
+fn include_dirs_are_searched() {
+	let dir = dir("include-dirs");
+	std::fs::create_dir_all(dir.join("inc")).unwrap();
+	write(&dir, "inc/defs.h", "#define VALUE 7\n");
+	write(&dir, "main.S", "#include <defs.h>\nVALUE\n");
+
+	let mut context = Context::new();
+	context.include_dir(dir.join("inc"));
+	assert_eq!(process_file(dir.join("main.S"), &mut context).unwrap(), "7\n");
+}
+
+
+#[test]
+fn include_dirs_come_from_the_command_line_and_environment() {
+	let dir = dir("include-options");
+	for sub in ["first", "second", "env"] {
+		std::fs::create_dir_all(dir.join(sub)).unwrap();
+	}
+	write(&dir, "first/value.h", "\tdb 1\n");
+	write(&dir, "second/value.h", "\tdb 2\n");
+	write(&dir, "second/other.h", "\tdb 3\n");
+	write(&dir, "env/env.h", "\tdb 4\n");
+
+	// the first directory that has the file wins
+	let source = "#include <value.h>\n#include <other.h>\n";
+	assert_eq!(assemble(&dir, source, &["-I", "first", "-Isecond"]).unwrap(), [1, 0, 0, 0, 3, 0, 0, 0]);
+
+	write(&dir, "input.S", "#include <env.h>\n");
+	let status = Command::new(env!("CARGO_BIN_EXE_rust_as"))
+		.current_dir(&dir)
+		.args(["input.S", "output.bin"])
+		.env("ASINCLUDE", dir.join("env"))
+		.status()
+		.unwrap();
+	assert!(status.success());
+	assert_eq!(std::fs::read(dir.join("output.bin")).unwrap(), [4, 0, 0, 0]);
+}
+
+
+#[test]
+fn includes_follow_conditions_and_macros() {
+	let dir = dir("include-directive");
+	std::fs::create_dir_all(dir.join("sub")).unwrap();
+	write(&dir, "board.h", "BOARD\n");
+	write(&dir, "sub/part.h", "#include \"near.h\"\n");
+	write(&dir, "sub/near.h", "NEAR\n");
+	write(&dir, "main.S", "\
+#if 0
+#include \"missing.h\"
+#endif
+#define HEADER \"board.h\"
+#include HEADER
+#include \"sub/part.h\"
+");
+
+	// a nested include is found next to the file that includes it
+	assert_eq!(process_file(dir.join("main.S"), &mut Context::new()).unwrap(), "BOARD\nNEAR\n");
+}
+
+
+#[test]
+fn missing_include_names_the_file() {
+	let dir = dir("include-missing");
+	write(&dir, "main.S", "#include <missing.h>\n");
+
+	let error = process_file(dir.join("main.S"), &mut Context::new()).unwrap_err().to_string();
+	assert!(error.contains("`missing.h`"), "{error}");
+}
@@ -0,0 +1,83 @@
+mod common;
+
+use rust_as::object::Object;
+
+use common::{assemble, dir, run, write};
+
+
+#[test]
+fn numeric_labels_refer_to_the_nearest_definition() {
+	let image = assemble(&dir("numeric-labels"), "
+1:
+	dl 1b, 1f
+1:
+	dl 1b, 1f
+1:
+", &[]).unwrap();
+
+	let words: Vec<u64> = image.chunks(8).map(|c| u64::from_le_bytes(c.try_into().unwrap())).collect();
+	assert_eq!(words, [0, 0x10, 0x10, 0x20]);
+}
+
+
+#[test]
+fn dot_labels_are_scoped_to_the_previous_label() {
+	let dir = dir("dot-labels");
+	let image = assemble(&dir, "
+first:
+.loop:
+	dl .loop
+second:
+	dl 0
+.loop:
+	dl .loop, first.loop, second.loop
+", &["-M", "out.map"]).unwrap();
+
+	let words: Vec<u64> = image.chunks(8).map(|c| u64::from_le_bytes(c.try_into().unwrap())).collect();
+	assert_eq!(words, [0, 0, 0x10, 0, 0x10]);
+
+	let map = std::fs::read_to_string(dir.join("out.map")).unwrap();
+	assert!(map.contains("0000000000000000 .text first.loop\n"), "{map}");
+	assert!(map.contains("0000000000000010 .text second.loop\n"), "{map}");
+}
+
+
+#[test]
+fn objects_export_local_labels_by_their_full_name() {
+	let dir = dir("labels-object");
+	write(&dir, "input.S", "
+.global func.loop
+func:
+1:
+.loop:
+	dl 1b
+");
+	run(env!("CARGO_BIN_EXE_rust_as"), &dir, &["input.S", "output.o", "-c"]).unwrap();
+	let object = Object::read(&std::fs::read(dir.join("output.o")).unwrap()).unwrap();
+
+	let names: Vec<(&str, bool)> = object.symbols.iter().map(|s| (s.name.as_str(), s.global)).collect();
+	assert!(names.contains(&("func", false)), "{names:?}");
+	assert!(names.contains(&("func.loop", true)), "{names:?}");
+	assert!(!names.iter().any(|(n, _)| *n == "1"), "{names:?}");
+}
+
+
+#[test]
+fn duplicate_labels_are_reported() {
+	let error = assemble(&dir("duplicate-labels"), "
+start:
+1:
+.loop:
+start:
+1:
+other:
+.loop:
+", &[]).unwrap_err();
+
+	assert!(error.contains("label `start` is already defined\n --> input.S:5:1"), "{error}");
+	assert_eq!(error.matches("is already defined").count(), 1, "{error}");
+
+	// numeric labels only look in the direction they are told to
+	let error = assemble(&dir("numeric-direction"), "\tdl 1b\n1:\n", &[]).unwrap_err();
+	assert!(error.contains("label `1b` not found"), "{error}");
+}
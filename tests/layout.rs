@@ -0,0 +1,80 @@
+mod common;
+
+use common::{assemble, dir};
+
+
+#[test]
+fn directives_move_the_location_counter() {
+	let image = assemble(&dir("layout-directives"), "
+	db 1
+	.org 8
+	db 2
+	.align 16
+	db 3
+	.space 3, 0xaa
+	.fill 2, 2, 0x1234
+	dl $
+", &[]).unwrap();
+
+	assert_eq!(image, [
+		1, 0, 0, 0, 0, 0, 0, 0,
+		2, 0, 0, 0, 0, 0, 0, 0,
+		3, 0, 0, 0,
+		0xaa, 0xaa, 0xaa, 0,
+		0x34, 0x12, 0x34, 0x12,
+		28, 0, 0, 0, 0, 0, 0, 0,
+	]);
+}
+
+
+#[test]
+fn sizes_may_use_earlier_labels() {
+	let image = assemble(&dir("layout-labels"), "
+start:
+	addn r1, r0, 1
+	.space 32 - ($ - start)
+header:
+	db 1, 2
+	.fill header + 12 - $, 1, 0xff
+end:
+	dl end
+", &[]).unwrap();
+
+	assert_eq!(image.len(), 32 + 12 + 8);
+	assert_eq!(&image[36..44], [0xff; 8]);
+	assert_eq!(&image[44..], &44u64.to_le_bytes());
+}
+
+
+#[test]
+fn sizes_may_not_use_later_labels() {
+	let error = assemble(&dir("layout-forward"), "
+	.space end - start
+start:
+	db 1
+end:
+	.space missing
+", &[]).unwrap_err();
+
+	assert!(error.contains("sizes may only use labels defined before them\n --> input.S:2:9"), "{error}");
+	assert!(error.contains("label `missing` not found"), "{error}");
+}
+
+
+#[test]
+fn bad_sizes_are_reported() {
+	let error = assemble(&dir("layout-errors"), "
+	dl 0, 0
+	.org 4
+	.align 3
+	.space -1
+	.fill 1, 9
+	.space 0x100000000
+", &[]).unwrap_err();
+
+	assert!(error.contains("`.org` cannot move the location counter backwards from 0x10 to 0x4"), "{error}");
+	assert!(error.contains("alignment 3 is not a power of two"), "{error}");
+	assert!(error.contains("expected a non-negative value, found -1"), "{error}");
+	assert!(error.contains("`.fill` size must be between 1 and 8, found 9"), "{error}");
+	assert!(error.contains("section `.text` grows beyond 0x100000000 bytes"), "{error}");
+}
@@ -0,0 +1,161 @@
+mod common;
+
+use rust_as::emu::Stop;
+use rust_as::object::Object;
+
+use common::{dir, emulate, run, write};
+
+
+const PROGRAM: &str = "
+.equ SIZE, end - table
+.global start
+start:
+	addn r1, r0, table
+	loa r2, r1, 8
+	addn r3, r0, SIZE
+	addn r4, r0, message
+	subn pc, pc, 12
+
+.data
+table:
+	dl 1, 2, 3
+end:
+
+.section .rodata
+message:
+	db 0x68, 0x69, 0
+";
+
+
+fn assemble(dir: &std::path::Path, input: &str, output: &str, args: &[&str]) {
+	run(env!("CARGO_BIN_EXE_rust_as"), dir, &[&[input, output], args].concat()).unwrap();
+}
+
+
+fn link(dir: &std::path::Path, args: &[&str]) -> Vec<u8> {
+	run(env!("CARGO_BIN_EXE_rust_ld"), dir, &[args, &["-o", "linked.bin"]].concat()).unwrap();
+	std::fs::read(dir.join("linked.bin")).unwrap()
+}
+
+
+#[test]
+fn linked_object_matches_flat_image() {
+	let dir = dir("link-flat");
+	write(&dir, "program.S", PROGRAM);
+
+	assemble(&dir, "program.S", "flat.bin", &[]);
+	assemble(&dir, "program.S", "program.o", &["-c"]);
+
+	let flat = std::fs::read(dir.join("flat.bin")).unwrap();
+	assert_eq!(link(&dir, &["program.o"]), flat);
+
+	let (machine, stop) = emulate(&flat);
+	assert_eq!(stop, Stop::Halted);
+	assert_eq!(machine.reg(2), 2);
+	assert_eq!(machine.reg(3), 24);
+}
+
+
+#[test]
+fn linked_object_matches_flat_image_at_base() {
+	let dir = dir("link-base");
+	write(&dir, "program.S", PROGRAM);
+
+	assemble(&dir, "program.S", "flat.bin", &["--base", "0x4000"]);
+	assemble(&dir, "program.S", "program.o", &["-c"]);
+
+	let flat = std::fs::read(dir.join("flat.bin")).unwrap();
+	assert_eq!(link(&dir, &["program.o", "--base", "0x4000"]), flat);
+}
+
+
+#[test]
+fn externs_are_resolved_across_objects() {
+	let dir = dir("link-extern");
+	write(&dir, "main.S", "
+.extern value, twice
+	addn r1, r0, value
+	loa r2, r1, 0
+	addn r3, r0, twice
+	subn pc, pc, 12
+");
+	write(&dir, "lib.S", "
+.global value, twice
+.equ twice, 42 * 2
+.data
+value:
+	dl 0x1234
+");
+
+	assemble(&dir, "main.S", "main.o", &["-c"]);
+	assemble(&dir, "lib.S", "lib.o", &["-c"]);
+
+	let (machine, stop) = emulate(&link(&dir, &["main.o", "lib.o"]));
+	assert_eq!(stop, Stop::Halted);
+	assert_eq!(machine.reg(2), 0x1234);
+	assert_eq!(machine.reg(3), 84);
+}
+
+
+#[test]
+fn undefined_extern_fails_to_link() {
+	let dir = dir("link-undefined");
+	write(&dir, "main.S", "
+.extern missing
+	addn r1, r0, missing
+");
+
+	assemble(&dir, "main.S", "main.o", &["-c"]);
+	let error = run(env!("CARGO_BIN_EXE_rust_ld"), &dir, &["main.o", "-o", "linked.bin"]).unwrap_err();
+	assert!(error.contains("missing"), "{error}");
+}
+
+
+#[test]
+fn section_start_matches_base_directive() {
+	let dir = dir("link-section-start");
+	write(&dir, "flat.S", &PROGRAM.replace(".data\n", ".data\n.base 0x8000\n"));
+	write(&dir, "program.S", PROGRAM);
+
+	assemble(&dir, "flat.S", "flat.bin", &[]);
+	assemble(&dir, "program.S", "program.o", &["-c"]);
+
+	// the section moves in memory but keeps its place in the image
+	let flat = std::fs::read(dir.join("flat.bin")).unwrap();
+	assert!(flat.len() < 0x100);
+	assert_eq!(link(&dir, &["program.o", "--section-start", ".data=0x8000"]), flat);
+}
+
+
+#[test]
+fn sections_past_the_address_space_fail_to_link() {
+	let dir = dir("link-overflow");
+	write(&dir, "main.S", "\taddn r1, r0, 1\n");
+
+	assemble(&dir, "main.S", "main.o", &["-c"]);
+	let error = run(env!("CARGO_BIN_EXE_rust_ld"), &dir, &["main.o", "--base", "0xfffffffffffffff8", "-o", "linked.bin"])
+		.unwrap_err();
+	assert!(error.contains("section `.text` does not fit in the 64-bit address space"), "{error}");
+}
+
+
+#[test]
+fn corrupt_objects_are_rejected() {
+	let dir = dir("link-corrupt");
+	write(&dir, "main.S", "\taddn r1, r0, 1\n");
+	assemble(&dir, "main.S", "main.o", &["-c"]);
+	let bytes = std::fs::read(dir.join("main.o")).unwrap();
+	assert!(Object::read(&bytes).is_ok());
+
+	let shoff = u64::from_le_bytes(bytes[40..48].try_into().unwrap()) as usize;
+	let patch = |at: usize, value: u64| {
+		let mut bytes = bytes.clone();
+		bytes[at..at + 8].copy_from_slice(&value.to_le_bytes());
+		Object::read(&bytes).unwrap_err()
+	};
+
+	// the section header table, then the offset and size of the first real section
+	assert!(patch(40, u64::MAX - 8).contains("unexpected end of file"));
+	assert!(patch(shoff + 64 + 24, u64::MAX - 8).contains("out of bounds"));
+	assert!(patch(shoff + 64 + 32, u64::MAX).contains("section larger than"));
+}
@@ -0,0 +1,52 @@
+mod common;
+
+use rust_as::listing::{read_listing, read_map};
+
+use common::{assemble, dir, write};
+
+
+#[test]
+fn listing_and_map_read_back() {
+	let dir = dir("listing");
+	write(&dir, "data.inc", "table:\n\tdl 1, 2\n");
+	assemble(&dir, "\
+.equ SIZE, 16
+start:
+	addn r1, r0, table
+	iint 3
+.data
+#include \"data.inc\"
+.text
+end:
+", &["--base", "0x100", "-l", "out.lst", "-M", "out.map"]).unwrap();
+
+	let listing = std::fs::read_to_string(dir.join("out.lst")).unwrap();
+	let addn = ".text    0000000000000100  06 01 00 30 10 01 00 00  input.S:3   addn r1, r0, table\n";
+	let rest = "         0000000000000108  00 00 00 00\n";
+	assert!(listing.contains(&format!("{addn}{rest}")), "{listing}");
+	assert!(listing.contains(".data    0000000000000110                           data.inc:1  table:\n"), "{listing}");
+
+	// only the first line of the items that produced bytes
+	assert_eq!(read_listing(&listing).unwrap(), [
+		(0x100, 3, "addn r1, r0, table".to_string()),
+		(0x10c, 4, "iint 3".to_string()),
+		(0x110, 2, "dl 1, 2".to_string()),
+	]);
+
+	let map = std::fs::read_to_string(dir.join("out.map")).unwrap();
+	assert!(map.starts_with("0000000000000010 *ABS* SIZE\n0000000000000100 .text start\n"), "{map}");
+	assert_eq!(read_map(&map).unwrap(), [
+		(0x10, "SIZE".to_string()),
+		(0x100, "start".to_string()),
+		(0x110, "table".to_string()),
+		(0x110, "end".to_string()),
+	]);
+}
+
+
+#[test]
+fn malformed_files_are_rejected() {
+	assert_eq!(read_map("0100 .text start\nnot-a-number .text x\n").unwrap_err(), "invalid address on line 2");
+	assert_eq!(read_map("0100 .text\n").unwrap_err(), "malformed map entry on line 1");
+	assert_eq!(read_listing(".text 0100\n").unwrap_err(), "malformed listing entry on line 1");
+}
@@ -0,0 +1,132 @@
+mod common;
+
+use common::{assemble, dir};
+
+
+#[test]
+fn parameters_defaults_and_keywords() {
+	let image = assemble(&dir("macro-params"), "
+.macro put reg, value=7, size=B
+	addn\\size \\reg, r0, \\value
+.endm
+	put r1
+	put r2, 3
+	put value=5, reg=r3, size=S
+", &[]).unwrap();
+
+	assert_eq!(image, [
+		0x06, 0x01, 0x00, 0x00, 7, 0, 0, 0, 0, 0, 0, 0,
+		0x06, 0x02, 0x00, 0x00, 3, 0, 0, 0, 0, 0, 0, 0,
+		0x06, 0x03, 0x00, 0x10, 5, 0, 0, 0, 0, 0, 0, 0,
+	]);
+}
+
+
+#[test]
+fn labels_are_unique_per_expansion() {
+	let image = assemble(&dir("macro-labels"), "
+.macro here
+here\\@:
+	dl here\\@
+.endm
+	here
+	here
+", &[]).unwrap();
+
+	assert_eq!(&image[..8], &0u64.to_le_bytes());
+	assert_eq!(&image[8..], &8u64.to_le_bytes());
+}
+
+
+#[test]
+fn exitm_leaves_the_macro() {
+	let image = assemble(&dir("macro-exitm"), "
+.macro first a, b=2
+	db \\a
+.if \\b == 2
+	.exitm
+.elseif \\b == 3
+	db 0xee
+.endif
+	db 0xff
+.endm
+	first 1
+	first 2, 3
+	first 3, 4
+", &[]).unwrap();
+
+	assert_eq!(image, [1, 0, 0, 0, 2, 0, 0, 0, 0xee, 0, 0, 0, 0xff, 0, 0, 0, 3, 0, 0, 0, 0xff, 0, 0, 0]);
+}
+
+
+#[test]
+fn macros_may_call_themselves() {
+	let image = assemble(&dir("macro-recursive"), "
+.macro count n
+	db \\n
+.if \\n > 0
+	count \\n - 1
+.endif
+.endm
+	count 3
+", &[]).unwrap();
+
+	assert_eq!(image, [3, 0, 0, 0, 2, 0, 0, 0, 1, 0, 0, 0, 0, 0, 0, 0]);
+}
+
+
+#[test]
+fn conditions_on_labels_are_left_to_the_assembler() {
+	let image = assemble(&dir("macro-label-condition"), "
+.macro pad
+.if $ < 8
+	db 1
+.endif
+.endm
+	pad
+	pad
+	pad
+", &[]).unwrap();
+
+	assert_eq!(image, [1, 0, 0, 0, 1, 0, 0, 0]);
+}
+
+
+#[test]
+fn macros_may_define_macros() {
+	let image = assemble(&dir("macro-nested"), "
+.macro outer value
+.macro inner
+	db \\value
+.endm
+	inner
+.endm
+	outer 1
+	outer 2
+", &[]).unwrap();
+
+	assert_eq!(image, [1, 0, 0, 0, 2, 0, 0, 0]);
+}
+
+
+#[test]
+fn bad_macros_are_reported() {
+	let cases = [
+		(".macro put a\n.endm\n\tput\n", "missing value for parameter `a` of macro `put`"),
+		(".macro put a\n.endm\n\tput 1, 2\n", "macro `put` takes 1 arguments, found more"),
+		(".macro put\n", "`.macro put` is missing its `.endm`"),
+		(".endm\n", "`.endm` without `.macro`"),
+		(".exitm\n", "`.exitm` outside of a macro"),
+		(".macro m\n.endm\n.macro m\n.endm\n", "macro `m` is already defined"),
+		(".macro m\n\tm\n.endm\n\tm\n", "macro `m` is nested too deeply"),
+		(
+			".macro m\n.if end\n\t.exitm\n.endif\n.endm\n\tm\nend:\n",
+			"`.exitm` inside a `.if` whose condition is only known after macro expansion",
+		),
+	];
+
+	for (source, message) in cases {
+		let error = assemble(&dir("macro-errors"), source, &[]).unwrap_err();
+		assert!(error.contains(message), "{source}\n{error}");
+	}
+}
@@ -0,0 +1,101 @@
+mod common;
+
+use rust_as::object::{Object, Target};
+
+use common::{dir, run, write};
+
+
+// assembles `source` with `-c` and reads the object back
+fn object(test: &str, source: &str) -> Object {
+	let dir = dir(test);
+	write(&dir, "input.S", source);
+	run(env!("CARGO_BIN_EXE_rust_as"), &dir, &["input.S", "output.o", "-c"]).unwrap();
+	Object::read(&std::fs::read(dir.join("output.o")).unwrap()).unwrap()
+}
+
+
+fn section(object: &Object, name: &str) -> usize {
+	object.sections.iter().position(|s| s.name == name).unwrap()
+}
+
+
+#[test]
+fn sections_keep_their_contents() {
+	let object = object("object-sections", "
+	addn r1, r0, 1
+.data
+	db 1, 2, 3
+.bss
+	.space 0x100
+");
+
+	let names: Vec<_> = object.sections.iter().map(|s| s.name.as_str()).collect();
+	assert_eq!(names, [".text", ".data", ".bss"]);
+
+	let text = &object.sections[section(&object, ".text")];
+	assert_eq!(text.data, [0x06, 0x01, 0x00, 0x30, 1, 0, 0, 0, 0, 0, 0, 0]);
+	assert!(!text.bss && text.relocs.is_empty());
+
+	let data = &object.sections[section(&object, ".data")];
+	assert_eq!(&data.data[..3], [1, 2, 3]);
+
+	// .bss only records its size
+	let bss = &object.sections[section(&object, ".bss")];
+	assert!(bss.bss && bss.data.is_empty());
+	assert_eq!(bss.size, 0x100);
+}
+
+
+#[test]
+fn symbols_and_relocations() {
+	let object = object("object-symbols", "
+.global start, SIZE
+.extern value
+.equ SIZE, 24
+start:
+	addn r1, r0, value + 8
+	addn r2, r0, table
+.data
+	dl 0
+table:
+	dl start, SIZE
+	di value
+");
+
+	let text = section(&object, ".text");
+	let data = section(&object, ".data");
+	let symbol = |name| object.symbols.iter().position(|s| s.name == name).unwrap();
+
+	let start = &object.symbols[symbol("start")];
+	assert_eq!((start.section, start.value, start.global), (Some(text), 0, true));
+	let table = &object.symbols[symbol("table")];
+	assert_eq!((table.section, table.value, table.global), (Some(data), 8, false));
+
+	// constants are exported as absolute symbols
+	let size = &object.symbols[symbol("SIZE")];
+	assert!(size.absolute && size.global && size.section.is_none());
+	assert_eq!(size.value, 24);
+
+	let value = &object.symbols[symbol("value")];
+	assert!(!value.absolute && value.global && value.section.is_none());
+
+	// references to externs go through the symbol, local labels through their section
+	let relocs: Vec<_> = object.sections[text].relocs.iter()
+		.map(|r| (r.offset, r.size, r.target, r.addend))
+		.collect();
+	assert_eq!(relocs, [
+		(4, 8, Target::Symbol(symbol("value")), 8),
+		(16, 8, Target::Section(data), 8),
+	]);
+
+	let relocs: Vec<_> = object.sections[data].relocs.iter()
+		.map(|r| (r.offset, r.size, r.target, r.addend))
+		.collect();
+	assert_eq!(relocs, [
+		(8, 8, Target::Section(text), 0),
+		(24, 4, Target::Symbol(symbol("value")), 0),
+	]);
+
+	// absolute values are written out directly
+	assert_eq!(&object.sections[data].data[16..24], &24u64.to_le_bytes());
+}
@@ -0,0 +1,61 @@
+mod common;
+
+use common::{assemble, dir};
+
+
+#[test]
+fn operands_are_encoded_in_the_header() {
+	let image = assemble(&dir("encoding"), "\
+	addn r1, r2, 5
+	addB r3, r4, r5
+	iint 0xab
+	pushS r6
+", &[]).unwrap();
+
+	assert_eq!(image, [
+		0x06, 0x21, 0x00, 0x30, 5, 0, 0, 0, 0, 0, 0, 0,
+		0x02, 0x43, 0x05, 0x00,
+		0x1b, 0x00, 0xa0, 0x3b,
+		0x18, 0x00, 0x06, 0x10,
+	]);
+}
+
+
+#[test]
+fn operand_count_is_checked() {
+	let error = assemble(&dir("count"), "\
+	addn r1, r2
+	chst r0, r1
+	iret r1
+", &[]).unwrap_err();
+
+	assert!(error.contains("`addn` takes 3 operands, found 2"), "{error}");
+	assert!(error.contains("`chst` takes 1 operand, found 2"), "{error}");
+	// instructions without operands end right after the mnemonic
+	assert!(error.contains("found register\n --> input.S:3:7"), "{error}");
+}
+
+
+#[test]
+fn operand_kinds_are_checked() {
+	let error = assemble(&dir("kinds"), "\
+	add r1, 5, label
+	addn r1, r2, r3
+label:
+", &[]).unwrap_err();
+
+	assert!(error.contains("operand 2 of `add` must be a register"), "{error}");
+	assert!(error.contains("operand 3 of `add` must be a register"), "{error}");
+	assert!(error.contains("operand 3 of `addn` must be an expression, not a register"), "{error}");
+	assert!(error.contains(" --> input.S:1:9"), "{error}");
+}
+
+
+#[test]
+fn num8_is_range_checked() {
+	assert!(assemble(&dir("num8-ok"), "\tiint 255\n\tiint -128\n", &[]).is_ok());
+
+	let error = assemble(&dir("num8"), "\tiint 256\n\tiint -129\n", &[]).unwrap_err();
+	assert!(error.contains("value 256 does not fit in 8 bits"), "{error}");
+	assert!(error.contains("value -129 does not fit in 8 bits"), "{error}");
+}
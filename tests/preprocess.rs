@@ -0,0 +1,135 @@
+mod common;
+
+use rust_as::minipre::{process_str, Context, Error};
+
+use common::{assemble, dir};
+
+
+fn expand(text: &str) -> String {
+	process_str(text, &mut Context::new()).unwrap()
+}
+
+
+#[test]
+fn function_macros() {
+	assert_eq!(expand("\
+#define SQUARE(x) ((x) * (x))
+#define ADD(a, b) a + b
+SQUARE(3) ADD(SQUARE(1), 2)
+"), "((3) * (3)) ((1) * (1)) + 2\n");
+}
+
+
+#[test]
+fn function_macro_name_without_arguments_is_left_alone() {
+	assert_eq!(expand("#define F(x) x\nF + 1\n"), "F + 1\n");
+}
+
+
+#[test]
+fn stringify_and_paste() {
+	assert_eq!(expand("\
+#define STR(x) #x
+#define CAT(a, b) a ## b
+#define LABEL(n) CAT(loop_, n):
+STR(hello world) CAT(r, 1) LABEL(2)
+"), "\"hello world\" r1 loop_2:\n");
+}
+
+
+#[test]
+fn undef() {
+	assert_eq!(expand("#define A 1\nA\n#undef A\nA\n"), "1\nA\n");
+}
+
+
+#[test]
+fn macros_expand_to_instructions() {
+	let image = assemble(&dir("function-macro"), "\
+#define LOAD(r, value) addn r, r0, value
+	LOAD(r1, 2 * 3)
+", &[]).unwrap();
+	assert_eq!(image, [0x06, 0x01, 0x00, 0x30, 6, 0, 0, 0, 0, 0, 0, 0]);
+}
+
+
+#[test]
+fn bad_macro_calls_are_reported() {
+	let error = assemble(&dir("macro-arguments"), "#define F(a, b) a b\nF(1)\n", &[]).unwrap_err();
+	assert!(error.contains("Wrong number of macro arguments\n --> input.S:2:1"), "{error}");
+
+	// parentheses keep commas inside one argument
+	assert_eq!(expand("#define F(a, b) a b\nF(1, (2, 3))\n"), "1 (2, 3)\n");
+
+	let error = assemble(&dir("macro-unterminated"), "#define G(x) x\nG(\n", &[]).unwrap_err();
+	assert!(error.contains("Unterminated macro argument list\n --> input.S:2:1"), "{error}");
+}
+
+
+#[test]
+fn if_expressions() {
+	assert_eq!(expand("\
+#define FOO 3
+#if FOO * 2 == 6 && (1 << 4) > 15
+yes
+#endif
+#if defined(BAR) || FOO % 2 == 0
+no
+#elif !defined BAR && FOO - 4 < 0
+elif
+#else
+else
+#endif
+#ifdef FOO
+defined
+#endif
+#ifndef FOO
+undefined
+#endif
+"), "yes\nelif\ndefined\n");
+}
+
+
+#[test]
+fn builtins_are_defined_for_ifdef_too() {
+	assert_eq!(expand("\
+#if defined(__LINE__) && defined __FILE__
+both
+#endif
+#ifdef __LINE__
+line
+#endif
+#ifndef __COUNTER__
+counter
+#endif
+"), "both\nline\n");
+}
+
+
+#[test]
+fn error_directive() {
+	match process_str("#if 1\n#error stop here\n#endif\n", &mut Context::new()) {
+		Err(Error::User { line, msg }) => {
+			assert_eq!(line, 2);
+			assert!(msg.contains("stop here"), "{msg}");
+		},
+		other => panic!("{other:?}"),
+	}
+}
+
+
+#[test]
+fn unterminated_if_is_reported() {
+	for (source, expected) in [("#if 1\nyes\n", 1), ("#ifdef A\n#endif\n#ifndef A\n#if 0\n#endif\n", 3)] {
+		match process_str(source, &mut Context::new()) {
+			Err(Error::Syntax { line, msg }) => {
+				assert_eq!(line, expected);
+				assert_eq!(msg, "Unterminated `#if`, expected `#endif`");
+			},
+			other => panic!("{other:?}"),
+		}
+	}
+
+	let error = assemble(&dir("unterminated-if"), "\tdb 1\n#if 1\n\tdb 2\n", &[]).unwrap_err();
+	assert!(error.contains("Unterminated `#if`, expected `#endif`\n --> input.S:2:1"), "{error}");
+}
@@ -0,0 +1,72 @@
+mod common;
+
+use rust_as::emu::Stop;
+
+use common::{assemble, dir, emulate};
+
+
+#[test]
+fn sections_are_laid_out_in_order() {
+	let image = assemble(&dir("sections-order"), "
+	addn r1, r0, value
+	addn r2, r0, buffer
+.data
+value:
+	dl 0x55
+.text
+	loa r3, r1, 0
+	subn pc, pc, 12
+.bss
+buffer:
+	.space 0x1000
+", &[]).unwrap();
+
+	// .text with both of its parts, then .data, .bss takes no bytes in the image
+	assert_eq!(image.len(), 12 * 4 + 8);
+	assert_eq!(&image[48..], &0x55u64.to_le_bytes());
+
+	let (machine, stop) = emulate(&image);
+	assert_eq!(stop, Stop::Halted);
+	assert_eq!(machine.reg(1), 48);
+	assert_eq!(machine.reg(2), 56);
+	assert_eq!(machine.reg(3), 0x55);
+}
+
+
+#[test]
+fn sections_are_aligned() {
+	let image = assemble(&dir("sections-align"), "
+	addn r1, r0, table
+	addn r2, r0, message
+.section .rodata, 64
+table:
+	db 1
+.data
+message:
+	db 2
+", &[]).unwrap();
+
+	let (machine, _) = emulate(&image);
+	assert_eq!(machine.reg(1), 64);
+	assert_eq!(machine.reg(2), 68);
+	assert_eq!(image.len(), 72);
+}
+
+
+#[test]
+fn bss_only_reserves_space() {
+	let error = assemble(&dir("sections-bss-data"), "
+.bss
+	dl 0
+	dl 1
+", &[]).unwrap_err();
+	assert!(error.contains("non-zero data in `.bss`\n --> input.S:4:2"), "{error}");
+
+	let error = assemble(&dir("sections-bss"), "
+.bss
+	addn r1, r0, 1
+.section .rodata, 3
+", &[]).unwrap_err();
+	assert!(error.contains("instructions are not allowed in `.bss`"), "{error}");
+	assert!(error.contains("alignment 3 is not a power of two"), "{error}");
+}
@@ -0,0 +1,41 @@
+mod common;
+
+use common::{assemble, dir, write};
+
+
+#[test]
+fn errors_point_into_included_files() {
+	let dir = dir("origin-include");
+	write(&dir, "inner.inc", "\n\taddn r2, r0, inner\n");
+	write(&dir, "outer.inc", "\taddn r1, r0, 1\n#include \"inner.inc\"\n\taddn r3, r0, outer\n");
+	let error = assemble(&dir, "\
+#if 0
+	addn r0, r0, skipped
+#endif
+#include \"outer.inc\"
+	addn r4, r0, main
+", &[]).unwrap_err();
+
+	assert!(error.contains("label `inner` not found\n --> inner.inc:2:15\n  |\n2 | \taddn r2, r0, inner\n"), "{error}");
+	assert!(error.contains("label `outer` not found\n --> outer.inc:3:15\n"), "{error}");
+	assert!(error.contains("label `main` not found\n --> input.S:5:15\n"), "{error}");
+	assert!(!error.contains("skipped"), "{error}");
+}
+
+
+#[test]
+fn errors_in_macros_point_at_their_use() {
+	let error = assemble(&dir("origin-macro"), "\
+#define LOAD(r, v) addn r, r0, v
+.macro put reg, value
+	addn \\reg, r0, \\value
+.endm
+
+	LOAD(r1, gone)
+	put r2, absent
+", &[]).unwrap_err();
+
+	// the expanded line is shown at the line of the use
+	assert!(error.contains("label `gone` not found\n --> input.S:6:15\n  |\n6 | \taddn r1, r0, gone\n"), "{error}");
+	assert!(error.contains("label `absent` not found\n --> input.S:7:15\n  |\n7 | \taddn r2, r0, absent\n"), "{error}");
+}